@@ -0,0 +1,67 @@
+//! Converts the NES 2.0 XML database (https://forums.nesdev.org/viewtopic.php?t=19940) to the line format of
+//! src/gamedb.txt, printed to stdout:
+//!
+//!     cargo run --example gamedb -- nes20db.xml >> src/gamedb.txt
+
+use std::fs;
+
+/// Value of `name="..."` in the first `<element ...>` of `game`
+fn attribute<'a>(game: &'a str, element: &str, name: &str) -> Option<&'a str> {
+    let start = game.find(&format!("<{} ", element))?;
+    let tag = &game[start..start + game[start..].find('>')?];
+    let value = &tag[tag.find(&format!(" {}=\"", name))? + name.len() + 3..];
+    Some(&value[..value.find('"')?])
+}
+
+fn size<'a>(game: &'a str, element: &str) -> &'a str {
+    attribute(game, element, "size").unwrap_or("0")
+}
+
+/// The last comment before the board, naming the dump, without its directory and extension
+fn title(game: &str) -> Option<&str> {
+    let start = game[..game.find("<pcb ")?].rfind("<!--")? + 4;
+    let comment = game[start..start + game[start..].find("-->")?].trim();
+    let name = comment.rsplit(['/', '\\']).next()?;
+    Some(name.rsplit_once('.').map_or(name, |(stem, _)| stem))
+}
+
+fn convert(game: &str) -> Option<String> {
+    let mirroring = match attribute(game, "pcb", "mirroring")? {
+        "H" => "H",
+        "V" => "V",
+        "4" => "4",
+        _ => "-",
+    };
+    // multi-region games (2) run fine as NTSC
+    let region = match attribute(game, "console", "region").unwrap_or("0") {
+        "1" => "P",
+        "3" => "D",
+        _ => "N",
+    };
+    Some(format!(
+        "{} {} {} {} {} {} {} {} {} {}",
+        attribute(game, "rom", "crc32")?,
+        attribute(game, "rom", "sha1").filter(|sha1| sha1.len() == 40).unwrap_or("-"),
+        attribute(game, "pcb", "mapper")?,
+        attribute(game, "pcb", "submapper").unwrap_or("0"),
+        mirroring,
+        size(game, "prgram"),
+        size(game, "prgnvram"),
+        size(game, "chrram"),
+        region,
+        title(game)?
+    ))
+}
+
+fn main() -> Result<(), String> {
+    let path = std::env::args().nth(1).ok_or("Usage: gamedb <nes20db.xml>")?;
+    let xml = fs::read_to_string(&path).map_err(|e| format!("Can not read {}: {}", path, e))?;
+    // each entry runs from the end of the previous one, taking in a comment placed before its <game>
+    for game in xml.split_inclusive("</game>").filter(|game| game.contains("<game>")) {
+        match convert(game) {
+            Some(line) => println!("{}", line),
+            None => eprintln!("skipping incomplete entry: {}", game.trim()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use crate::cartridge::Region;
+
+/// Output unit periods in CPU cycles https://www.nesdev.org/wiki/APU_DMC
+const NTSC_RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+const PAL_RATES: [u16; 16] = [
+    398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50,
+];
+
+const SAMPLE_ADDRESS_BASE: u16 = 0xC000;
+
+/// Delta modulation channel, $4010-$4013: plays 1-bit delta encoded samples from CPU memory.
+///
+/// The channel can't reach memory itself: it asks for the next byte through `dma_address` and the bus,
+/// after reading it the way the CPU would (mappers see it, the CPU stalls), hands it over with `fill`.
+pub(super) struct Dmc {
+    rates: &'static [u16; 16],
+    irq_enabled: bool,
+    looping: bool,
+    timer_period: u16,
+    timer: u16,
+
+    sample_address: u16,
+    sample_length: u16,
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    level: u8,
+    irq: bool,
+}
+
+impl Dmc {
+    pub(super) fn new() -> Self {
+        Dmc {
+            rates: &NTSC_RATES,
+            irq_enabled: false,
+            looping: false,
+            timer_period: NTSC_RATES[0],
+            timer: 0,
+            sample_address: SAMPLE_ADDRESS_BASE,
+            sample_length: 1,
+            current_address: SAMPLE_ADDRESS_BASE,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            level: 0,
+            irq: false,
+        }
+    }
+
+    /// Dendy keeps the NTSC rates
+    pub(super) fn set_region(&mut self, region: Region) {
+        self.rates = if region == Region::Pal { &PAL_RATES } else { &NTSC_RATES };
+    }
+
+    /// `register` is the address offset within the channel, 0-3
+    pub(super) fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // IL-- RRRR: IRQ enabled, loop, rate index
+            0 => {
+                self.irq_enabled = value & 0b1000_0000 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = value & 0b0100_0000 != 0;
+                self.timer_period = self.rates[(value & 0b1111) as usize];
+            }
+            // -DDD DDDD: output level
+            1 => self.level = value & 0b0111_1111,
+            // sample address $C000 + A * 64
+            2 => self.sample_address = SAMPLE_ADDRESS_BASE + value as u16 * 64,
+            // sample length L * 16 + 1 bytes
+            3 => self.sample_length = value as u16 * 16 + 1,
+            _ => {}
+        }
+    }
+
+    /// $4015 bit 4: disabling stops the sample after the byte in the buffer, enabling (re)starts it
+    /// unless it is still playing. Either way acknowledges the IRQ.
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.current_address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    /// Sample bytes left to read
+    pub(super) fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    pub(super) fn irq(&self) -> bool {
+        self.irq
+    }
+
+    /// Address of the byte the memory reader wants now that the sample buffer is empty
+    pub(super) fn dma_address(&self) -> Option<u16> {
+        if self.sample_buffer.is_none() && self.bytes_remaining > 0 {
+            Some(self.current_address)
+        } else {
+            None
+        }
+    }
+
+    /// The byte read for `dma_address`
+    pub(super) fn fill(&mut self, value: u8) {
+        self.sample_buffer = Some(value);
+        // the address wraps around to $8000
+        self.current_address = self.current_address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+
+    /// Clocked every CPU cycle, the rates being in CPU cycles
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.timer_period - 1;
+        if !self.silence {
+            if self.shift_register & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+            self.shift_register >>= 1;
+        }
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(value) => {
+                    self.silence = false;
+                    self.shift_register = value;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    /// Current level, 0-127
+    pub(super) fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Runs `cycles` CPU cycles, serving the memory reader from `memory` (indexed from $C000)
+    fn run(dmc: &mut Dmc, memory: &[u8], cycles: usize) -> Vec<u16> {
+        let mut reads = Vec::new();
+        for _ in 0..cycles {
+            dmc.clock_timer();
+            if let Some(address) = dmc.dma_address() {
+                reads.push(address);
+                dmc.fill(memory[(address - SAMPLE_ADDRESS_BASE) as usize]);
+            }
+        }
+        reads
+    }
+
+    #[test]
+    fn test_sample_playback() {
+        let mut dmc = Dmc::new();
+        // fastest rate, 17 bytes at $C040, starting from level 64
+        dmc.write_register(0, 0x0F);
+        dmc.write_register(1, 64);
+        dmc.write_register(2, 1);
+        dmc.write_register(3, 1);
+        dmc.set_enabled(true);
+        let mut memory = vec![0; 0x100];
+        memory[0x40] = 0b0000_1111;
+        let reads = run(&mut dmc, &memory, 1);
+        assert_eq!(reads, [0xC040]);
+
+        // the first output cycle plays silence, then the byte: up 4 times, down 4 times
+        run(&mut dmc, &memory, 54 * 8 - 1);
+        assert_eq!(dmc.output(), 64);
+        run(&mut dmc, &memory, 54 * 4);
+        assert_eq!(dmc.output(), 72);
+        run(&mut dmc, &memory, 54 * 4);
+        assert_eq!(dmc.output(), 64);
+
+        let reads = run(&mut dmc, &memory, 54 * 8 * 17);
+        assert_eq!(reads.len(), 14);
+        assert_eq!(reads.last(), Some(&0xC050));
+        assert!(!dmc.active());
+        assert!(!dmc.irq());
+    }
+
+    #[test]
+    fn test_loop_and_irq() {
+        let mut dmc = Dmc::new();
+        dmc.write_register(0, 0b0100_1111);
+        dmc.set_enabled(true);
+        let memory = vec![0; 0x10];
+        let reads = run(&mut dmc, &memory, 54 * 8 * 3);
+        assert_eq!(reads, [0xC000; 4]);
+        assert!(dmc.active());
+
+        // leaving the loop finishes the sample and raises the IRQ
+        dmc.write_register(0, 0b1000_1111);
+        run(&mut dmc, &memory, 54 * 8);
+        assert!(!dmc.active());
+        assert!(dmc.irq());
+        dmc.write_register(0, 0x0F);
+        assert!(!dmc.irq());
+    }
+}
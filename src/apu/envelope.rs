@@ -0,0 +1,84 @@
+/// Volume envelope https://www.nesdev.org/wiki/APU_Envelope
+///
+/// Either a constant volume or a saw decaying from 15 to 0, one step every (period + 1) quarter frames,
+/// optionally looping. Shared by the pulse and noise channels.
+#[derive(Default)]
+pub(super) struct Envelope {
+    start: bool,
+    looping: bool,
+    constant_volume: bool,
+    /// Constant volume, or the divider period
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Low 6 bits of $4000/$4004/$400C: --LC VVVV
+    pub(super) fn write_control(&mut self, value: u8) {
+        self.looping = value & 0b0010_0000 != 0;
+        self.constant_volume = value & 0b0001_0000 != 0;
+        self.volume = value & 0b0000_1111;
+    }
+
+    /// Writes to the channel's length register restart the envelope on the next quarter frame
+    pub(super) fn restart(&mut self) {
+        self.start = true;
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if self.constant_volume {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_decay_and_loop() {
+        let mut envelope = Envelope::default();
+        envelope.write_control(0b0001_0111);
+        assert_eq!(envelope.output(), 7);
+
+        // period 1: one step every 2 quarter frames
+        envelope.write_control(0b0000_0001);
+        envelope.restart();
+        envelope.clock_quarter_frame();
+        assert_eq!(envelope.output(), 15);
+        for _ in 0..2 * 15 {
+            envelope.clock_quarter_frame();
+        }
+        assert_eq!(envelope.output(), 0);
+        for _ in 0..4 {
+            envelope.clock_quarter_frame();
+        }
+        assert_eq!(envelope.output(), 0);
+
+        envelope.write_control(0b0010_0001);
+        envelope.clock_quarter_frame();
+        envelope.clock_quarter_frame();
+        assert_eq!(envelope.output(), 15);
+    }
+}
@@ -0,0 +1,180 @@
+use crate::cartridge::Region;
+
+/// CPU cycles of the frame counter steps after a reset https://www.nesdev.org/wiki/APU_Frame_Counter.
+/// The 4-step sequence ends with the fourth, the 5-step one with the fifth; Dendy keeps the NTSC timing.
+const NTSC_STEPS: [u32; 5] = [7457, 14913, 22371, 29829, 37281];
+const PAL_STEPS: [u32; 5] = [8313, 16627, 24939, 33253, 41565];
+
+/// What a frame counter cycle clocks in the channels
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) enum FrameClock {
+    None,
+    /// Envelopes and the triangle's linear counter
+    Quarter,
+    /// The quarter frame units, plus length counters and sweeps
+    Half,
+}
+
+/// Frame counter, $4017: MI-- ---- (5-step mode, IRQ inhibit)
+///
+/// In 4-step mode it raises the frame IRQ around its last step, 5-step mode never does. A write resets the
+/// sequence 3 or 4 CPU cycles later, depending on the cycle parity; switching to 5-step mode also clocks
+/// the quarter and half frame units right then.
+pub(super) struct FrameCounter {
+    steps: &'static [u32; 5],
+    five_step: bool,
+    irq_inhibit: bool,
+    irq: bool,
+    cycle: u32,
+    /// Value written and CPU cycles left before it takes effect
+    pending_write: Option<(u8, u8)>,
+}
+
+impl FrameCounter {
+    pub(super) fn new() -> Self {
+        FrameCounter {
+            steps: &NTSC_STEPS,
+            five_step: false,
+            irq_inhibit: false,
+            irq: false,
+            cycle: 0,
+            pending_write: None,
+        }
+    }
+
+    pub(super) fn set_region(&mut self, region: Region) {
+        self.steps = if region == Region::Pal { &PAL_STEPS } else { &NTSC_STEPS };
+    }
+
+    /// `odd_cycle` tells whether the write happens on an APU cycle (odd CPU cycle), which shortens the delay
+    pub(super) fn write(&mut self, value: u8, odd_cycle: bool) {
+        self.irq_inhibit = value & 0b0100_0000 != 0;
+        if self.irq_inhibit {
+            self.irq = false;
+        }
+        self.pending_write = Some((value, if odd_cycle { 3 } else { 4 }));
+    }
+
+    /// The reset button acts as a write of the last mode
+    pub(super) fn reset(&mut self) {
+        self.irq = false;
+        let value = if self.five_step { 0b1000_0000 } else { 0 } | if self.irq_inhibit { 0b0100_0000 } else { 0 };
+        self.pending_write = Some((value, 4));
+    }
+
+    pub(super) fn irq(&self) -> bool {
+        self.irq
+    }
+
+    /// $4015 reads acknowledge the frame IRQ
+    pub(super) fn clear_irq(&mut self) {
+        self.irq = false;
+    }
+
+    /// Advances one CPU cycle
+    pub(super) fn tick(&mut self) -> FrameClock {
+        if let Some((value, delay)) = self.pending_write {
+            if delay > 1 {
+                self.pending_write = Some((value, delay - 1));
+            } else {
+                self.pending_write = None;
+                self.five_step = value & 0b1000_0000 != 0;
+                self.cycle = 0;
+                return if self.five_step {
+                    FrameClock::Half
+                } else {
+                    FrameClock::None
+                };
+            }
+        }
+
+        self.cycle += 1;
+        let [quarter1, half1, quarter2, last4, last5] = *self.steps;
+        let last = if self.five_step { last5 } else { last4 };
+        if !self.five_step && (last4 - 1..=last4 + 1).contains(&self.cycle) && !self.irq_inhibit {
+            self.irq = true;
+        }
+        match self.cycle {
+            cycle if cycle == quarter1 || cycle == quarter2 => FrameClock::Quarter,
+            cycle if cycle == half1 || cycle == last => FrameClock::Half,
+            cycle if cycle == last + 1 => {
+                self.cycle = 0;
+                FrameClock::None
+            }
+            _ => FrameClock::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Cycle numbers of the quarter and half frame clocks over `cycles` cycles
+    fn run(frame_counter: &mut FrameCounter, cycles: u32) -> Vec<(u32, FrameClock)> {
+        (1..=cycles)
+            .map(|cycle| (cycle, frame_counter.tick()))
+            .filter(|(_, clock)| *clock != FrameClock::None)
+            .collect()
+    }
+
+    #[test]
+    fn test_four_step_sequence_and_irq() {
+        let mut frame_counter = FrameCounter::new();
+        let clocks = run(&mut frame_counter, 29829);
+        assert_eq!(
+            clocks,
+            [
+                (7457, FrameClock::Quarter),
+                (14913, FrameClock::Half),
+                (22371, FrameClock::Quarter),
+                (29829, FrameClock::Half)
+            ]
+        );
+        assert!(frame_counter.irq());
+        frame_counter.clear_irq();
+        // the flag is set again on the cycle after the last step
+        frame_counter.tick();
+        assert!(frame_counter.irq());
+        frame_counter.clear_irq();
+        assert_eq!(run(&mut frame_counter, 7457).last(), Some(&(7457, FrameClock::Quarter)));
+        assert!(!frame_counter.irq());
+    }
+
+    #[test]
+    fn test_five_step_write() {
+        let mut frame_counter = FrameCounter::new();
+        run(&mut frame_counter, 100);
+        // 5-step mode with IRQ inhibit on an even cycle: reset 4 cycles later, with a half frame clock
+        frame_counter.write(0b1100_0000, false);
+        let clocks = run(&mut frame_counter, 4 + 37282);
+        assert_eq!(
+            clocks,
+            [
+                (4, FrameClock::Half),
+                (4 + 7457, FrameClock::Quarter),
+                (4 + 14913, FrameClock::Half),
+                (4 + 22371, FrameClock::Quarter),
+                (4 + 37281, FrameClock::Half)
+            ]
+        );
+        assert!(!frame_counter.irq());
+
+        // back to 4-step mode on an odd cycle: 3 cycles of delay
+        frame_counter.write(0x00, true);
+        let clocks = run(&mut frame_counter, 3 + 7457);
+        assert_eq!(clocks, [(3 + 7457, FrameClock::Quarter)]);
+    }
+
+    #[test]
+    fn test_inhibit_clears_irq() {
+        let mut frame_counter = FrameCounter::new();
+        frame_counter.set_region(Region::Pal);
+        run(&mut frame_counter, 33253);
+        assert!(frame_counter.irq());
+        frame_counter.write(0b0100_0000, false);
+        assert!(!frame_counter.irq());
+        run(&mut frame_counter, 33254);
+        assert!(!frame_counter.irq());
+    }
+}
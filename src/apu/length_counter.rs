@@ -0,0 +1,46 @@
+/// Lengths loaded by the top 5 bits of $4003/$4007/$400B/$400F, in half frames
+const LENGTHS: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16,
+    28, 32, 30,
+];
+
+/// Length counter https://www.nesdev.org/wiki/APU_Length_Counter
+///
+/// Silences its channel once it counts down to 0, unless halted. Disabling the channel in $4015 clears
+/// it and keeps it from loading.
+#[derive(Default)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    halt: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub(super) fn set_halt(&mut self, halt: bool) {
+        self.halt = halt;
+    }
+
+    /// `value` is the whole length register, the index sits in its top 5 bits
+    pub(super) fn load(&mut self, value: u8) {
+        if self.enabled {
+            self.counter = LENGTHS[(value >> 3) as usize];
+        }
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        if !self.halt && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
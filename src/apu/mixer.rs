@@ -0,0 +1,41 @@
+/// Non-linear DAC https://www.nesdev.org/wiki/APU_Mixer: the pulse channels share one resistor network and
+/// triangle, noise and DMC another, each approximated by a lookup table on the summed levels.
+pub(super) struct Mixer {
+    pulse_table: [f32; 31],
+    tnd_table: [f32; 203],
+}
+
+impl Mixer {
+    pub(super) fn new() -> Self {
+        let mut pulse_table = [0.0; 31];
+        for (n, level) in pulse_table.iter_mut().enumerate().skip(1) {
+            *level = 95.52 / (8128.0 / n as f32 + 100.0);
+        }
+        let mut tnd_table = [0.0; 203];
+        for (n, level) in tnd_table.iter_mut().enumerate().skip(1) {
+            *level = 163.67 / (24329.0 / n as f32 + 100.0);
+        }
+        Mixer { pulse_table, tnd_table }
+    }
+
+    /// Output level between 0 and 1 from the channel levels (see `Apu::outputs`)
+    pub(super) fn mix(&self, [pulse1, pulse2, triangle, noise, dmc]: [u8; 5]) -> f32 {
+        self.pulse_table[(pulse1 + pulse2) as usize]
+            + self.tnd_table[3 * triangle as usize + 2 * noise as usize + dmc as usize]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_mix_levels() {
+        let mixer = Mixer::new();
+        assert_eq!(mixer.mix([0; 5]), 0.0);
+        assert!((mixer.mix([15, 15, 0, 0, 0]) - 0.2575).abs() < 0.0001);
+        assert!((mixer.mix([0, 0, 15, 15, 127]) - 0.7425).abs() < 0.0001);
+        // not linear: two pulses at 8 are quieter than twice one
+        assert!(mixer.mix([8, 8, 0, 0, 0]) < 2.0 * mixer.mix([8, 0, 0, 0, 0]));
+    }
+}
@@ -0,0 +1,267 @@
+mod dmc;
+mod envelope;
+mod frame_counter;
+mod length_counter;
+mod mixer;
+mod noise;
+mod output;
+mod pulse;
+mod resampler;
+mod triangle;
+
+use crate::cartridge::Region;
+use dmc::Dmc;
+use frame_counter::{FrameClock, FrameCounter};
+use mixer::Mixer;
+use noise::Noise;
+use output::Output;
+use pulse::{Pulse, SweepNegate};
+use triangle::Triangle;
+
+pub const APU_REGISTERS: u16 = 0x4000;
+pub const APU_REGISTERS_END: u16 = 0x4013;
+/// Channel enables on writes, channel and IRQ status on reads
+pub const APU_STATUS: u16 = 0x4015;
+/// Write only, reads are the second controller's
+pub const APU_FRAME_COUNTER: u16 = 0x4017;
+
+const PULSE1: u16 = 0x4000;
+const PULSE1_END: u16 = 0x4003;
+const PULSE2: u16 = 0x4004;
+const PULSE2_END: u16 = 0x4007;
+const TRIANGLE: u16 = 0x4008;
+const TRIANGLE_END: u16 = 0x400B;
+const NOISE: u16 = 0x400C;
+const NOISE_END: u16 = 0x400F;
+const DMC: u16 = 0x4010;
+const DMC_END: u16 = 0x4013;
+
+/// Audio Processing Unit https://www.nesdev.org/wiki/APU
+///
+/// Clocked once per CPU cycle by the bus. The pulse timers run on APU cycles (every other CPU cycle), the
+/// others on CPU cycles (the noise and DMC periods being given in CPU cycles); envelopes, sweeps and length
+/// counters on the frame counter's quarter and half frames.
+///
+/// Sound is only produced once `set_sample_rate` is called, headless runs skip the mixing altogether.
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    frame_counter: FrameCounter,
+    /// CPU cycles since power on, its parity picks the APU cycles
+    cycles: u64,
+    region: Region,
+    mixer: Mixer,
+    output: Option<Output>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Apu::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulse1: Pulse::new(SweepNegate::OnesComplement),
+            pulse2: Pulse::new(SweepNegate::TwosComplement),
+            triangle: Triangle::default(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            frame_counter: FrameCounter::new(),
+            cycles: 0,
+            region: Region::Ntsc,
+            mixer: Mixer::new(),
+            output: None,
+        }
+    }
+
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.noise.set_region(region);
+        self.dmc.set_region(region);
+        self.frame_counter.set_region(region);
+        if let Some(sample_rate) = self.output.as_ref().map(Output::sample_rate) {
+            self.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Starts producing mono samples at `sample_rate` Hz, see `take_samples`
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.output = Some(Output::new(self.region.cpu_clock_rate(), sample_rate));
+    }
+
+    /// Appends the samples produced since the last call to `out`, nothing until `set_sample_rate` is called
+    pub fn take_samples(&mut self, out: &mut Vec<i16>) {
+        if let Some(output) = &mut self.output {
+            output.take_samples(out);
+        }
+    }
+
+    /// Reset button: every channel is silenced and the frame counter restarts in its current mode
+    pub fn reset(&mut self) {
+        self.write_status(0);
+        self.frame_counter.reset();
+    }
+
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            PULSE1..=PULSE1_END => self.pulse1.write_register(address - PULSE1, value),
+            PULSE2..=PULSE2_END => self.pulse2.write_register(address - PULSE2, value),
+            TRIANGLE..=TRIANGLE_END => self.triangle.write_register(address - TRIANGLE, value),
+            NOISE..=NOISE_END => self.noise.write_register(address - NOISE, value),
+            DMC..=DMC_END => self.dmc.write_register(address - DMC, value),
+            APU_STATUS => self.write_status(value),
+            APU_FRAME_COUNTER => self.frame_counter.write(value, self.cycles % 2 == 1),
+            _ => {}
+        }
+    }
+
+    /// ---D NT21: enables the DMC, noise, triangle and pulse channels
+    fn write_status(&mut self, value: u8) {
+        self.pulse1.set_enabled(value & 0b0000_0001 != 0);
+        self.pulse2.set_enabled(value & 0b0000_0010 != 0);
+        self.triangle.set_enabled(value & 0b0000_0100 != 0);
+        self.noise.set_enabled(value & 0b0000_1000 != 0);
+        self.dmc.set_enabled(value & 0b0001_0000 != 0);
+    }
+
+    /// IF-D NT21: DMC IRQ, frame IRQ, DMC bytes left, length counters above 0. Acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let flags = [
+            self.pulse1.active(),
+            self.pulse2.active(),
+            self.triangle.active(),
+            self.noise.active(),
+            self.dmc.active(),
+            false,
+            self.frame_counter.irq(),
+            self.dmc.irq(),
+        ];
+        self.frame_counter.clear_irq();
+        flags
+            .iter()
+            .enumerate()
+            .fold(0, |status, (bit, flag)| status | (*flag as u8) << bit)
+    }
+
+    /// Advances one CPU cycle
+    pub fn tick(&mut self) {
+        match self.frame_counter.tick() {
+            FrameClock::Quarter => self.clock_quarter_frame(),
+            FrameClock::Half => self.clock_half_frame(),
+            FrameClock::None => {}
+        }
+        if self.cycles % 2 == 1 {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        self.cycles += 1;
+        let level = self.output.is_some().then(|| self.mixer.mix(self.outputs()));
+        if let (Some(output), Some(level)) = (&mut self.output, level) {
+            output.clock(level);
+        }
+    }
+
+    /// Address the DMC wants read, the bus answers with `dmc_fill` and stalls the CPU
+    pub fn dmc_dma_address(&self) -> Option<u16> {
+        self.dmc.dma_address()
+    }
+
+    pub fn dmc_fill(&mut self, value: u8) {
+        self.dmc.fill(value);
+    }
+
+    /// The frame counter and the DMC share the CPU IRQ line
+    pub fn irq_pending(&self) -> bool {
+        self.frame_counter.irq() || self.dmc.irq()
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse1.clock_quarter_frame();
+        self.pulse2.clock_quarter_frame();
+        self.triangle.clock_quarter_frame();
+        self.noise.clock_quarter_frame();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.clock_quarter_frame();
+        self.pulse1.clock_half_frame();
+        self.pulse2.clock_half_frame();
+        self.triangle.clock_half_frame();
+        self.noise.clock_half_frame();
+    }
+
+    /// Levels of pulse 1, pulse 2, triangle, noise (0-15) and DMC (0-127)
+    pub fn outputs(&self) -> [u8; 5] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            self.triangle.output(),
+            self.noise.output(),
+            self.dmc.output(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pulse_registers() {
+        let mut apu = Apu::new();
+        apu.write_register(APU_STATUS, 0b0000_0011);
+        // pulse 1 at 75% duty (its first step is high), pulse 2 at 12.5% (its first step is low)
+        apu.write_register(0x4000, 0b1101_0101);
+        apu.write_register(0x4002, 0x80);
+        apu.write_register(0x4003, 0b0000_1000);
+        apu.write_register(0x4004, 0b0001_1010);
+        apu.write_register(0x4006, 0x80);
+        apu.write_register(0x4007, 0b0000_1000);
+        assert_eq!(apu.outputs()[..2], [5, 0]);
+
+        // the timers run on every other CPU cycle: the first step change takes 2 cycles
+        apu.tick();
+        assert_eq!(apu.outputs()[..2], [5, 0]);
+        apu.tick();
+        assert_eq!(apu.outputs()[..2], [0, 10]);
+
+        // length counters run out after 254 half frames (the envelope being constant)
+        for _ in 0..254 {
+            apu.clock_half_frame();
+        }
+        apu.tick();
+        apu.tick();
+        assert_eq!(apu.outputs()[..2], [0, 0]);
+    }
+
+    #[test]
+    fn test_status_and_frame_irq() {
+        let mut apu = Apu::new();
+        apu.write_register(APU_STATUS, 0b0000_1001);
+        apu.write_register(0x4003, 0b0000_1000);
+        apu.write_register(0x4007, 0b0000_1000);
+        apu.write_register(0x400F, 0b0000_1000);
+        assert_eq!(apu.read_status(), 0b0000_1001);
+
+        // 4-step mode raises the frame IRQ, reading the status acknowledges it
+        for _ in 0..29829 {
+            apu.tick();
+        }
+        assert!(apu.irq_pending());
+        assert_eq!(apu.read_status(), 0b0100_1001);
+        assert!(!apu.irq_pending());
+        assert_eq!(apu.read_status(), 0b0000_1001);
+
+        // disabling a channel clears its length counter
+        apu.write_register(APU_STATUS, 0b0000_1000);
+        assert_eq!(apu.read_status(), 0b0000_1000);
+    }
+}
@@ -0,0 +1,145 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+use crate::cartridge::Region;
+
+/// Timer periods in CPU cycles https://www.nesdev.org/wiki/APU_Noise
+const NTSC_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+const PAL_PERIODS: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+
+/// Noise channel, $400C-$400F: a 15-bit linear feedback shift register
+pub(super) struct Noise {
+    periods: &'static [u16; 16],
+    timer_period: u16,
+    timer: u16,
+    /// Feedback from bit 6 instead of bit 1: 93 or 31 step sequences instead of 32767
+    short_mode: bool,
+    shift_register: u16,
+    envelope: Envelope,
+    length: LengthCounter,
+}
+
+impl Noise {
+    pub(super) fn new() -> Self {
+        Noise {
+            periods: &NTSC_PERIODS,
+            timer_period: NTSC_PERIODS[0],
+            timer: 0,
+            short_mode: false,
+            shift_register: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    /// Dendy keeps the NTSC rates
+    pub(super) fn set_region(&mut self, region: Region) {
+        self.periods = if region == Region::Pal {
+            &PAL_PERIODS
+        } else {
+            &NTSC_PERIODS
+        };
+    }
+
+    /// `register` is the address offset within the channel, 0-3
+    pub(super) fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // --LC VVVV: length counter halt / envelope loop, constant volume, volume / envelope period
+            0 => {
+                self.length.set_halt(value & 0b0010_0000 != 0);
+                self.envelope.write_control(value);
+            }
+            // M--- PPPP: mode, period index
+            2 => {
+                self.short_mode = value & 0b1000_0000 != 0;
+                self.timer_period = self.periods[(value & 0b1111) as usize];
+            }
+            // LLLL L---: length counter load
+            3 => {
+                self.length.load(value);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    /// Clocked every CPU cycle, the periods being in CPU cycles
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock_quarter_frame();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock_half_frame();
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.length.active()
+    }
+
+    /// Current level, 0-15
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active() || self.shift_register & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Steps until the shift register comes back to its starting value
+    fn sequence_length(short_mode: bool) -> usize {
+        let mut noise = Noise::new();
+        noise.write_register(2, if short_mode { 0x80 } else { 0x00 });
+        let start = noise.shift_register;
+        (1..=32767)
+            .find(|_| {
+                for _ in 0..4 {
+                    noise.clock_timer();
+                }
+                noise.shift_register == start
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_shift_register_modes() {
+        assert_eq!(sequence_length(false), 32767);
+        assert_eq!(sequence_length(true), 93);
+    }
+
+    #[test]
+    fn test_output_and_region_periods() {
+        let mut noise = Noise::new();
+        noise.set_enabled(true);
+        noise.write_register(0, 0b0001_1100);
+        noise.write_register(3, 0b0000_1000);
+        // bit 0 of the shift register mutes the output
+        assert_eq!(noise.output(), 0);
+        noise.clock_timer();
+        assert_eq!(noise.output(), 12);
+
+        noise.set_region(Region::Pal);
+        noise.write_register(2, 0x0F);
+        assert_eq!(noise.timer_period, 3778);
+    }
+}
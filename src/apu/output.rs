@@ -0,0 +1,111 @@
+use super::resampler::Resampler;
+use std::f32::consts::PI;
+
+/// First-order filter, at the output sample rate
+struct Filter {
+    high_pass: bool,
+    alpha: f32,
+    previous_input: f32,
+    previous_output: f32,
+}
+
+impl Filter {
+    fn new(high_pass: bool, cutoff: f32, sample_rate: f32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate;
+        Filter {
+            high_pass,
+            alpha: if high_pass { rc / (rc + dt) } else { dt / (rc + dt) },
+            previous_input: 0.0,
+            previous_output: 0.0,
+        }
+    }
+
+    fn apply(&mut self, input: f32) -> f32 {
+        let output = if self.high_pass {
+            self.alpha * (self.previous_output + input - self.previous_input)
+        } else {
+            self.previous_output + self.alpha * (input - self.previous_output)
+        };
+        self.previous_input = input;
+        self.previous_output = output;
+        output
+    }
+}
+
+/// What happens to the mixer level between the APU and the speaker: resampling to the output rate, then the
+/// console's output stage https://www.nesdev.org/wiki/APU_Mixer, high-pass filters at 90 and 440 Hz and a
+/// low-pass one at 14 kHz. The high-pass filters also take out the DC offset of the unsigned mixer level.
+pub(super) struct Output {
+    resampler: Resampler,
+    filters: [Filter; 3],
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl Output {
+    pub(super) fn new(clock_rate: f64, sample_rate: u32) -> Self {
+        let rate = sample_rate as f32;
+        Output {
+            resampler: Resampler::new(clock_rate, sample_rate as f64),
+            filters: [
+                Filter::new(true, 90.0, rate),
+                Filter::new(true, 440.0, rate),
+                Filter::new(false, 14_000.0, rate),
+            ],
+            sample_rate,
+            samples: Vec::new(),
+        }
+    }
+
+    pub(super) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Advances one CPU cycle with the mixer level
+    pub(super) fn clock(&mut self, level: f32) {
+        self.resampler.clock(level);
+    }
+
+    /// Appends the samples produced since the last call to `out`
+    pub(super) fn take_samples(&mut self, out: &mut Vec<i16>) {
+        self.samples.clear();
+        self.resampler.read(&mut self.samples);
+        for sample in &self.samples {
+            let filtered = self
+                .filters
+                .iter_mut()
+                .fold(*sample, |level, filter| filter.apply(level));
+            out.push((filtered * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_removes_dc_and_keeps_tones() {
+        let mut output = Output::new(48_000.0, 48_000);
+        let mut samples = Vec::new();
+        // a constant level fades out
+        for _ in 0..48_000 {
+            output.clock(0.5);
+        }
+        output.take_samples(&mut samples);
+        assert!(*samples.iter().max().unwrap() > 10_000);
+        assert!(samples.last().unwrap().abs() < 10);
+
+        // a 2 kHz square wave goes through, centred on 0
+        samples.clear();
+        for cycle in 0..48_000 {
+            output.clock(if cycle / 12 % 2 == 0 { 0.5 } else { 0.0 });
+        }
+        output.take_samples(&mut samples);
+        let tail = &samples[samples.len() - 480..];
+        assert!(*tail.iter().max().unwrap() > 5_000);
+        assert!(*tail.iter().min().unwrap() < -5_000);
+        assert!(tail.iter().map(|sample| *sample as i32).sum::<i32>().abs() < 480 * 100);
+    }
+}
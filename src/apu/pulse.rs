@@ -0,0 +1,248 @@
+use super::envelope::Envelope;
+use super::length_counter::LengthCounter;
+
+/// Waveforms for the four duty settings, in sequencer order https://www.nesdev.org/wiki/APU_Pulse
+const DUTY_SEQUENCES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+/// Periods below this, or sweep targets above `MAX_PERIOD`, mute the channel
+const MIN_PERIOD: u16 = 8;
+const MAX_PERIOD: u16 = 0x7FF;
+
+/// Pulse 1 negates with the ones' complement (one less than pulse 2), pulse 2 with the two's complement
+#[derive(Clone, Copy, PartialEq)]
+pub(super) enum SweepNegate {
+    OnesComplement,
+    TwosComplement,
+}
+
+/// Sweep unit https://www.nesdev.org/wiki/APU_Sweep
+struct Sweep {
+    negate_mode: SweepNegate,
+    enabled: bool,
+    period: u8,
+    negate: bool,
+    shift: u8,
+    divider: u8,
+    reload: bool,
+}
+
+impl Sweep {
+    fn target_period(&self, period: u16) -> u16 {
+        let change = period >> self.shift;
+        if !self.negate {
+            period + change
+        } else if self.negate_mode == SweepNegate::OnesComplement {
+            period.saturating_sub(change + 1)
+        } else {
+            period.saturating_sub(change)
+        }
+    }
+}
+
+/// One of the two square wave channels, $4000-$4003 and $4004-$4007
+pub(super) struct Pulse {
+    duty: usize,
+    step: usize,
+    timer_period: u16,
+    timer: u16,
+    envelope: Envelope,
+    sweep: Sweep,
+    length: LengthCounter,
+}
+
+impl Pulse {
+    pub(super) fn new(negate_mode: SweepNegate) -> Self {
+        Pulse {
+            duty: 0,
+            step: 0,
+            timer_period: 0,
+            timer: 0,
+            envelope: Envelope::default(),
+            sweep: Sweep {
+                negate_mode,
+                enabled: false,
+                period: 0,
+                negate: false,
+                shift: 0,
+                divider: 0,
+                reload: false,
+            },
+            length: LengthCounter::default(),
+        }
+    }
+
+    /// `register` is the address offset within the channel, 0-3
+    pub(super) fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // DDLC VVVV: duty, length counter halt / envelope loop, constant volume, volume / envelope period
+            0 => {
+                self.duty = (value >> 6) as usize;
+                self.length.set_halt(value & 0b0010_0000 != 0);
+                self.envelope.write_control(value);
+            }
+            // EPPP NSSS: sweep enabled, period, negate, shift
+            1 => {
+                self.sweep.enabled = value & 0b1000_0000 != 0;
+                self.sweep.period = (value >> 4) & 0b111;
+                self.sweep.negate = value & 0b0000_1000 != 0;
+                self.sweep.shift = value & 0b111;
+                self.sweep.reload = true;
+            }
+            2 => self.timer_period = (self.timer_period & 0xFF00) | value as u16,
+            // LLLL LHHH: length counter load, timer high bits. Restarts the sequencer and the envelope.
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0b111) << 8);
+                self.length.load(value);
+                self.step = 0;
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    /// Clocked every APU cycle (2 CPU cycles)
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        self.envelope.clock_quarter_frame();
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock_half_frame();
+        let target = self.sweep.target_period(self.timer_period);
+        if self.sweep.divider == 0 && self.sweep.enabled && self.sweep.shift != 0 && !self.muted(target) {
+            self.timer_period = target;
+        }
+        if self.sweep.divider == 0 || self.sweep.reload {
+            self.sweep.divider = self.sweep.period;
+            self.sweep.reload = false;
+        } else {
+            self.sweep.divider -= 1;
+        }
+    }
+
+    /// The sweep unit mutes the channel whether it is enabled or not
+    fn muted(&self, target: u16) -> bool {
+        self.timer_period < MIN_PERIOD || target > MAX_PERIOD
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.length.active()
+    }
+
+    /// Current level, 0-15
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted(self.sweep.target_period(self.timer_period))
+            || DUTY_SEQUENCES[self.duty][self.step] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playing_pulse(negate_mode: SweepNegate) -> Pulse {
+        let mut pulse = Pulse::new(negate_mode);
+        pulse.set_enabled(true);
+        // duty 50%, constant volume 9, period $100, length index 1 (254)
+        pulse.write_register(0, 0b1001_1001);
+        pulse.write_register(2, 0x00);
+        pulse.write_register(3, 0b0000_1001);
+        pulse
+    }
+
+    #[test]
+    fn test_duty_sequence() {
+        let mut pulse = playing_pulse(SweepNegate::TwosComplement);
+        let mut wave = Vec::new();
+        for _ in 0..8 {
+            wave.push(pulse.output());
+            // one step every period + 1 APU cycles
+            for _ in 0..=0x100 {
+                pulse.clock_timer();
+            }
+        }
+        assert_eq!(wave, [0, 9, 9, 9, 9, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_length_counter() {
+        let mut pulse = playing_pulse(SweepNegate::TwosComplement);
+        assert!(pulse.active());
+        for _ in 0..253 {
+            pulse.clock_half_frame();
+        }
+        assert!(pulse.active());
+        pulse.clock_half_frame();
+        assert!(!pulse.active());
+
+        // disabled channels don't load
+        pulse.set_enabled(false);
+        pulse.write_register(3, 0b0000_1001);
+        assert!(!pulse.active());
+
+        // halted counters keep their value
+        pulse.set_enabled(true);
+        pulse.write_register(0, 0b0010_0000);
+        pulse.write_register(3, 0b0000_1001);
+        pulse.clock_half_frame();
+        assert!(pulse.active());
+    }
+
+    #[test]
+    fn test_sweep_negate_differs_between_channels() {
+        let mut pulse1 = playing_pulse(SweepNegate::OnesComplement);
+        let mut pulse2 = playing_pulse(SweepNegate::TwosComplement);
+        // enabled, period 0, negate, shift 1
+        for pulse in [&mut pulse1, &mut pulse2] {
+            pulse.write_register(1, 0b1000_1001);
+            pulse.clock_half_frame();
+        }
+        assert_eq!(pulse1.timer_period, 0x100 - 0x80 - 1);
+        assert_eq!(pulse2.timer_period, 0x100 - 0x80);
+    }
+
+    #[test]
+    fn test_sweep_muting() {
+        let mut pulse = playing_pulse(SweepNegate::TwosComplement);
+        pulse.step = 1;
+        assert_eq!(pulse.output(), 9);
+
+        // a target period above $7FF mutes even with the sweep disabled
+        pulse.write_register(3, 0b0000_1110);
+        pulse.step = 1;
+        assert_eq!(pulse.output(), 0);
+        pulse.write_register(1, 0b0000_0001);
+        pulse.clock_half_frame();
+        assert_eq!(pulse.timer_period, 0x600);
+
+        // so does a period below 8
+        pulse.write_register(1, 0b0000_1000);
+        pulse.write_register(2, 0x07);
+        pulse.write_register(3, 0b0000_1000);
+        pulse.step = 1;
+        assert_eq!(pulse.output(), 0);
+    }
+}
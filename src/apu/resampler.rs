@@ -0,0 +1,116 @@
+use std::f64::consts::PI;
+
+/// Output samples each level change spreads over
+const KERNEL_WIDTH: usize = 16;
+/// Sub-sample positions the kernels are computed for
+const PHASES: usize = 32;
+/// Cutoff as a fraction of the output sample rate, a little under Nyquist
+const CUTOFF: f64 = 0.45;
+
+/// Band-limited resampling from the CPU clock rate to the output sample rate, the way blargg's blip_buf does.
+///
+/// The APU level only changes now and then, so instead of filtering 1.79 million samples per second each
+/// change is added to the output as a band-limited (windowed sinc) impulse at its exact position, and the
+/// output is the running sum of the impulses: band-limited steps, no aliasing from the square waves.
+pub(super) struct Resampler {
+    /// Output samples per clock
+    ratio: f64,
+    kernels: Vec<[f32; KERNEL_WIDTH]>,
+    /// Impulses added to the samples not read yet
+    buffer: Vec<f32>,
+    /// Position of the current clock, in samples from the start of `buffer`
+    position: f64,
+    level: f32,
+    sum: f32,
+}
+
+impl Resampler {
+    pub(super) fn new(clock_rate: f64, sample_rate: f64) -> Self {
+        let kernels = (0..=PHASES)
+            .map(|phase| {
+                let offset = phase as f64 / PHASES as f64;
+                let mut kernel = [0.0; KERNEL_WIDTH];
+                for (tap, value) in kernel.iter_mut().enumerate() {
+                    // distance from the impulse, centred in the kernel
+                    let x = tap as f64 - offset - (KERNEL_WIDTH / 2) as f64 + 1.0;
+                    let sinc = if x == 0.0 {
+                        1.0
+                    } else {
+                        (2.0 * PI * CUTOFF * x).sin() / (2.0 * PI * CUTOFF * x)
+                    };
+                    // Blackman window over the kernel width
+                    let w = (x + KERNEL_WIDTH as f64 / 2.0) / KERNEL_WIDTH as f64;
+                    let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                    *value = (sinc * window) as f32;
+                }
+                // every step must end exactly at its new level
+                let total: f32 = kernel.iter().sum();
+                kernel.iter_mut().for_each(|value| *value /= total);
+                kernel
+            })
+            .collect();
+        Resampler {
+            ratio: sample_rate / clock_rate,
+            kernels,
+            buffer: vec![0.0; KERNEL_WIDTH],
+            position: 0.0,
+            level: 0.0,
+            sum: 0.0,
+        }
+    }
+
+    /// Advances one clock with the level `level`
+    pub(super) fn clock(&mut self, level: f32) {
+        if level != self.level {
+            let delta = level - self.level;
+            self.level = level;
+            let index = self.position as usize;
+            let phase = ((self.position - index as f64) * PHASES as f64).round() as usize;
+            if self.buffer.len() < index + KERNEL_WIDTH {
+                self.buffer.resize(index + KERNEL_WIDTH, 0.0);
+            }
+            for (sample, value) in self.buffer[index..].iter_mut().zip(&self.kernels[phase]) {
+                *sample += delta * value;
+            }
+        }
+        self.position += self.ratio;
+    }
+
+    /// Moves the finished samples (those no later change can reach) to `out`
+    pub(super) fn read(&mut self, out: &mut Vec<f32>) {
+        let count = self.position as usize;
+        if self.buffer.len() < count + KERNEL_WIDTH {
+            self.buffer.resize(count + KERNEL_WIDTH, 0.0);
+        }
+        for impulse in self.buffer.drain(..count) {
+            self.sum += impulse;
+            out.push(self.sum);
+        }
+        self.position -= count as f64;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_band_limited_step() {
+        let mut resampler = Resampler::new(1_789_773.0, 48_000.0);
+        let mut samples = Vec::new();
+        for clock in 0..1_789_773 / 10 {
+            resampler.clock(if clock < 1_789_773 / 20 { 0.0 } else { 0.5 });
+        }
+        resampler.read(&mut samples);
+        assert!((samples.len() as i32 - 4800).abs() <= 1);
+
+        // flat before and after the step, which rings a little around its position only
+        let step = 2400;
+        assert!(samples[..step - KERNEL_WIDTH].iter().all(|sample| *sample == 0.0));
+        assert!(samples[step + KERNEL_WIDTH..]
+            .iter()
+            .all(|sample| (sample - 0.5).abs() < 1e-4));
+        let overshoot = samples.iter().cloned().fold(0.0, f32::max);
+        assert!(overshoot > 0.5 && overshoot < 0.55);
+    }
+}
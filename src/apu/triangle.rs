@@ -0,0 +1,124 @@
+use super::length_counter::LengthCounter;
+
+/// 32-step triangle wave
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+/// Triangle channel, $4008-$400B https://www.nesdev.org/wiki/APU_Triangle
+///
+/// Its timer runs on CPU cycles, twice as fast as the other channels', and the sequencer only moves while
+/// both the linear and the length counter are non-zero. Stopping leaves the output where it was.
+#[derive(Default)]
+pub(super) struct Triangle {
+    step: usize,
+    timer_period: u16,
+    timer: u16,
+    /// Also halts the length counter
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    length: LengthCounter,
+}
+
+impl Triangle {
+    /// `register` is the address offset within the channel, 0-3
+    pub(super) fn write_register(&mut self, register: u16, value: u8) {
+        match register {
+            // CRRR RRRR: control / length counter halt, linear counter reload value
+            0 => {
+                self.control = value & 0b1000_0000 != 0;
+                self.length.set_halt(self.control);
+                self.linear_reload_value = value & 0b0111_1111;
+            }
+            2 => self.timer_period = (self.timer_period & 0xFF00) | value as u16,
+            // LLLL LHHH: length counter load, timer high bits
+            3 => {
+                self.timer_period = (self.timer_period & 0x00FF) | ((value as u16 & 0b111) << 8);
+                self.length.load(value);
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.length.set_enabled(enabled);
+    }
+
+    /// Clocked every CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.timer_period;
+            if self.linear_counter > 0 && self.length.active() {
+                self.step = (self.step + 1) % SEQUENCE.len();
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn clock_quarter_frame(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    pub(super) fn clock_half_frame(&mut self) {
+        self.length.clock_half_frame();
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.length.active()
+    }
+
+    /// Current level, 0-15
+    pub(super) fn output(&self) -> u8 {
+        SEQUENCE[self.step]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_linear_counter_gates_the_sequencer() {
+        let mut triangle = Triangle::default();
+        triangle.set_enabled(true);
+        // linear counter 2, period 0: one step per CPU cycle
+        triangle.write_register(0, 0x02);
+        triangle.write_register(2, 0x00);
+        triangle.write_register(3, 0b0000_1000);
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 15);
+
+        triangle.clock_quarter_frame();
+        for _ in 0..4 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.output(), 11);
+
+        // the reload flag was cleared with the control bit clear, the counter runs out after 2 quarters
+        triangle.clock_quarter_frame();
+        triangle.clock_quarter_frame();
+        for _ in 0..4 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.output(), 11);
+
+        // writing $400B sets the reload flag again
+        triangle.write_register(3, 0b0000_1000);
+        triangle.clock_quarter_frame();
+        for _ in 0..3 {
+            triangle.clock_timer();
+        }
+        assert_eq!(triangle.output(), 8);
+    }
+}
@@ -0,0 +1,174 @@
+use std::io::{Cursor, Read};
+
+use flate2::read::GzDecoder;
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const SEVEN_ZIP_MAGIC: &[u8] = &[0x37, 0x7A, 0xBC, 0xAF, 0x27, 0x1C];
+const ROM_EXTENSIONS: [&str; 3] = ["nes", "fds", "nsf"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Zip,
+    Gzip,
+    SevenZip,
+}
+
+/// Detects the container by its magic bytes, None for anything else (a plain ROM image)
+pub fn detect(data: &[u8]) -> Option<Format> {
+    if data.starts_with(ZIP_MAGIC) {
+        Some(Format::Zip)
+    } else if data.starts_with(GZIP_MAGIC) {
+        Some(Format::Gzip)
+    } else if data.starts_with(SEVEN_ZIP_MAGIC) {
+        Some(Format::SevenZip)
+    } else {
+        None
+    }
+}
+
+fn is_rom_name(name: &str) -> bool {
+    match name.rsplit_once('.') {
+        Some((_, extension)) => ROM_EXTENSIONS.iter().any(|rom| extension.eq_ignore_ascii_case(rom)),
+        None => false,
+    }
+}
+
+/// Names of the `.nes`, `.fds` and `.nsf` entries of an archive, sorted.
+/// A gzip stream holds a single file and is listed under its stored name whatever the extension.
+pub fn rom_entries(data: &[u8]) -> Result<Vec<String>, String> {
+    match detect(data) {
+        Some(Format::Zip) => {
+            let archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Bad zip archive: {}", e))?;
+            let mut names: Vec<String> = archive
+                .file_names()
+                .filter(|name| is_rom_name(name))
+                .map(str::to_string)
+                .collect();
+            names.sort();
+            Ok(names)
+        }
+        Some(Format::Gzip) => {
+            let decoder = GzDecoder::new(data);
+            let name = decoder.header().and_then(|header| header.filename());
+            Ok(vec![name.map_or("rom".to_string(), |name| {
+                String::from_utf8_lossy(name).to_string()
+            })])
+        }
+        Some(Format::SevenZip) => {
+            let reader =
+                sevenz_rust::SevenZReader::new(Cursor::new(data), data.len() as u64, sevenz_rust::Password::empty())
+                    .map_err(|e| format!("Bad 7z archive: {}", e))?;
+            let mut names: Vec<String> = reader
+                .archive()
+                .files
+                .iter()
+                .filter(|entry| !entry.is_directory() && is_rom_name(entry.name()))
+                .map(|entry| entry.name().to_string())
+                .collect();
+            names.sort();
+            Ok(names)
+        }
+        None => Err("File is not a zip, gzip or 7z archive".to_string()),
+    }
+}
+
+/// Decompresses one entry of an archive
+pub fn read_entry(data: &[u8], name: &str) -> Result<Vec<u8>, String> {
+    let mut content = vec![];
+    match detect(data) {
+        Some(Format::Zip) => {
+            let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|e| format!("Bad zip archive: {}", e))?;
+            let mut file = archive.by_name(name).map_err(|e| format!("{}: {}", name, e))?;
+            file.read_to_end(&mut content).map_err(|e| format!("{}: {}", name, e))?;
+        }
+        Some(Format::Gzip) => {
+            GzDecoder::new(data)
+                .read_to_end(&mut content)
+                .map_err(|e| format!("Bad gzip stream: {}", e))?;
+        }
+        Some(Format::SevenZip) => {
+            let mut reader =
+                sevenz_rust::SevenZReader::new(Cursor::new(data), data.len() as u64, sevenz_rust::Password::empty())
+                    .map_err(|e| format!("Bad 7z archive: {}", e))?;
+            let mut found = false;
+            reader
+                .for_each_entries(|entry, entry_reader| {
+                    if entry.name() != name {
+                        return Ok(true);
+                    }
+                    found = true;
+                    entry_reader.read_to_end(&mut content)?;
+                    Ok(false)
+                })
+                .map_err(|e| format!("{}: {}", name, e))?;
+            if !found {
+                return Err(format!("{} is not in the archive", name));
+            }
+        }
+        None => return Err("File is not a zip, gzip or 7z archive".to_string()),
+    }
+    Ok(content)
+}
+
+/// Returns the ROM image inside an archive, or the data untouched when it is not an archive.
+///
+/// When the archive holds several ROMs `choose` gets their names and returns the index to load,
+/// None gives up with an error listing them.
+pub fn extract_rom(data: Vec<u8>, choose: &dyn Fn(&[String]) -> Option<usize>) -> Result<Vec<u8>, String> {
+    if detect(&data).is_none() {
+        return Ok(data);
+    }
+    let entries = rom_entries(&data)?;
+    let name = match entries.len() {
+        0 => return Err("Archive contains no .nes, .fds or .nsf file".to_string()),
+        1 => &entries[0],
+        _ => match choose(&entries) {
+            Some(index) if index < entries.len() => &entries[index],
+            _ => return Err(format!("Archive contains several ROMs: {}", entries.join(", "))),
+        },
+    };
+    read_entry(&data, name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, content) in files {
+            writer.start_file(*name, zip::write::FileOptions::default()).unwrap();
+            writer.write_all(content).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_zip() {
+        let data = zip_archive(&[("readme.txt", b"hello"), ("Game.NES", b"NES\x1arom")]);
+        assert_eq!(detect(&data), Some(Format::Zip));
+        assert_eq!(extract_rom(data, &|_| None).unwrap(), b"NES\x1arom");
+
+        let data = zip_archive(&[("a.nes", b"first"), ("b.fds", b"second")]);
+        assert_eq!(rom_entries(&data).unwrap(), vec!["a.nes", "b.fds"]);
+        assert_eq!(extract_rom(data.clone(), &|_| Some(1)).unwrap(), b"second");
+        assert_eq!(
+            extract_rom(data, &|_| None).unwrap_err(),
+            "Archive contains several ROMs: a.nes, b.fds"
+        );
+    }
+
+    #[test]
+    fn test_gzip_and_plain() {
+        let mut encoder = flate2::GzBuilder::new()
+            .filename("game.nes")
+            .write(vec![], flate2::Compression::default());
+        encoder.write_all(b"NES\x1arom").unwrap();
+        let data = encoder.finish().unwrap();
+        assert_eq!(rom_entries(&data).unwrap(), vec!["game.nes"]);
+        assert_eq!(extract_rom(data, &|_| None).unwrap(), b"NES\x1arom");
+        assert_eq!(extract_rom(b"NES\x1a".to_vec(), &|_| None).unwrap(), b"NES\x1a");
+    }
+}
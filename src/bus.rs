@@ -1,3 +1,15 @@
+use std::cell::{Cell, Ref, RefCell};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::apu::{Apu, APU_FRAME_COUNTER, APU_REGISTERS, APU_REGISTERS_END, APU_STATUS};
+use crate::cartridge::{Cartridge, Region};
+use crate::joypad::Joypad;
+use crate::mapper::fds::Fds;
+use crate::mapper::{self, MapperRef};
+use crate::ppu::Ppu;
+use crate::save::{self, SaveFile};
+
 pub trait Memory {
     fn mem_read(&self, address: u16) -> u8;
     fn mem_write(&mut self, address: u16, value: u8);
@@ -9,47 +21,312 @@ const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const OAM_DMA: u16 = 0x4014;
+const JOYPAD1: u16 = 0x4016;
+const JOYPAD2: u16 = 0x4017;
+const CARTRIDGE_SPACE: u16 = 0x4020;
+const CARTRIDGE_SPACE_END: u16 = 0xFFFF;
 
 pub struct Bus {
-    cpu_vram: [u8; 0x800]
+    cpu_vram: [u8; 0x800],
+    /// Register reads have side effects (vblank flag, read buffer) while `Memory::mem_read` takes `&self`
+    ppu: RefCell<Ppu>,
+    /// Reading the status acknowledges the frame IRQ
+    apu: RefCell<Apu>,
+    /// Reading a controller shifts its register
+    joypads: RefCell<[Joypad; 2]>,
+    /// Controller port read by the current instruction, for DMC DMA conflicts
+    joypad_read: Cell<Option<usize>>,
+    mapper: Option<MapperRef>,
+    /// Same mapper as `mapper` when a disk is loaded, for the disk side API
+    fds: Option<Rc<RefCell<Fds>>>,
+    battery: bool,
+    save_file: Option<SaveFile>,
+    cycles: usize,
+    /// Set by a $4014 write, the CPU halts for the transfer at the end of the instruction
+    oam_dma_pending: bool,
+    region: Region,
+    /// PPU dots owed to the PPU, in fifths of a dot: PAL runs 3.2 dots per CPU cycle
+    ppu_dot_fifths: u32,
 }
+
+impl Default for Bus {
+    fn default() -> Self {
+        Bus::new()
+    }
+}
+
 impl Bus { 
     pub fn new() -> Self {
-        Bus { cpu_vram: [0; 0x800] }
+        Bus {
+            cpu_vram: [0; 0x800],
+            ppu: RefCell::new(Ppu::new()),
+            apu: RefCell::new(Apu::new()),
+            joypads: RefCell::new([Joypad::new(), Joypad::new()]),
+            joypad_read: Cell::new(None),
+            mapper: None,
+            fds: None,
+            battery: false,
+            save_file: None,
+            cycles: 0,
+            oam_dma_pending: false,
+            region: Region::Ntsc,
+            ppu_dot_fifths: 0,
+        }
+    }
+
+    pub fn load_cartridge(&mut self, cartridge: &Cartridge) -> Result<(), String> {
+        self.save_file = None;
+        let mapper = mapper::create(cartridge)?;
+        self.ppu.borrow_mut().set_mapper(mapper.clone());
+        self.mapper = Some(mapper);
+        self.fds = None;
+        self.battery = cartridge.battery;
+        self.set_region(cartridge.region);
+        Ok(())
+    }
+
+    /// Loads a Famicom Disk System image, `bios` being the 8KB disk system BIOS ROM
+    pub fn load_fds(&mut self, cartridge: &Cartridge, bios: &[u8]) -> Result<(), String> {
+        self.save_file = None;
+        let fds = Rc::new(RefCell::new(Fds::new(cartridge, bios)?));
+        self.ppu.borrow_mut().set_mapper(fds.clone());
+        self.mapper = Some(fds.clone());
+        self.fds = Some(fds);
+        self.battery = cartridge.battery;
+        self.set_region(cartridge.region);
+        Ok(())
+    }
+
+    /// Machine timing, taken from the cartridge when it is loaded and overridable afterwards
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.ppu_dot_fifths = 0;
+        self.ppu.get_mut().set_region(region);
+        self.apu.get_mut().set_region(region);
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// The disk system when an FDS image is loaded, to switch disk sides
+    pub fn fds(&self) -> Option<&Rc<RefCell<Fds>>> {
+        self.fds.as_ref()
+    }
+
+    /// Loads the `.sav` file next to the ROM when the cartridge has a battery and keeps it up to date
+    pub fn attach_save_file(&mut self, rom_path: &Path) -> Result<(), String> {
+        if let (true, Some(mapper)) = (self.battery, &self.mapper) {
+            self.save_file = Some(SaveFile::open(SaveFile::path_for(rom_path), mapper.clone())?);
+        }
+        Ok(())
+    }
+
+    pub fn flush_save_file(&mut self) -> Result<(), String> {
+        match &mut self.save_file {
+            Some(save_file) => save_file.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Raw battery backed RAM (disk contents for the FDS), empty when the cartridge has no battery
+    pub fn save_data(&self) -> Vec<u8> {
+        match (self.battery, &self.mapper) {
+            (true, Some(mapper)) => mapper.borrow().save_data().to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn set_save_data(&mut self, data: &[u8]) {
+        if let Some(mapper) = &self.mapper {
+            save::load_into(mapper, data);
+        }
+    }
+
+    /// Reads without side effects, for traces and debuggers: None for the I/O registers, whose reads
+    /// change state, and for the mapper registers below $6000
+    pub fn peek(&self, address: u16) -> Option<u8> {
+        match (address, &self.mapper) {
+            (RAM..=RAM_MIRRORS_END, _) => Some(self.cpu_vram[(address & 0b111_1111_1111) as usize]),
+            (0x6000..=CARTRIDGE_SPACE_END, Some(mapper)) => Some(mapper.borrow_mut().peek(address)),
+            _ => None,
+        }
+    }
+
+    /// Reset button: the PPU, the APU and the controllers go back to their reset state, the CPU reloads its vector
+    pub fn reset(&mut self) {
+        self.ppu.get_mut().reset();
+        self.apu.get_mut().reset();
+        for joypad in self.joypads.get_mut() {
+            joypad.write(0);
+        }
+        self.oam_dma_pending = false;
+    }
+
+    pub fn tick(&mut self, cycles: u8) {
+        let instruction_cycles = cycles as usize;
+        let mut cycles = instruction_cycles;
+        if std::mem::take(&mut self.oam_dma_pending) {
+            // https://www.nesdev.org/wiki/PPU_registers#OAMDMA: one halt cycle, one more to align
+            // on an even (get) cycle, then 256 read/write pairs
+            cycles += 513 + (self.cycles + cycles) % 2;
+        }
+        let joypad_read = self.joypad_read.take();
+        let mut cycle = 0;
+        while cycle < cycles {
+            self.clock();
+            if let Some(address) = self.apu.get_mut().dmc_dma_address() {
+                let in_oam_dma = cycle >= instruction_cycles;
+                // the halt lands on the instruction's last cycle, where it reads: a controller read is
+                // repeated and the extra clock drops a bit https://www.nesdev.org/wiki/DMA#Register_conflicts
+                if let (true, Some(port)) = (cycle + 1 == instruction_cycles, joypad_read) {
+                    self.joypads.get_mut()[port].read();
+                }
+                cycles += self.dmc_dma(address, in_oam_dma);
+            }
+            cycle += 1;
+        }
+        self.cycles += cycles;
+        if let Some(save_file) = &mut self.save_file {
+            save_file.tick(cycles);
+        }
+    }
+
+    /// One CPU cycle for everything clocked alongside the CPU
+    fn clock(&mut self) {
+        if let Some(mapper) = &self.mapper {
+            mapper.borrow_mut().cpu_clock();
+        }
+        self.apu.get_mut().tick();
+        self.ppu_dot_fifths += 5 * self.region.cpu_clock_divider() / self.region.ppu_clock_divider();
+        let ppu = self.ppu.get_mut();
+        while self.ppu_dot_fifths >= 5 {
+            self.ppu_dot_fifths -= 5;
+            ppu.tick();
+        }
+    }
+
+    /// DMC sample fetch https://www.nesdev.org/wiki/DMA#DMC_DMA: read through the bus like a CPU read, so
+    /// mappers see it. Returns the cycles the CPU is halted: usually 4, 2 when it slips into an OAM DMA.
+    fn dmc_dma(&mut self, address: u16, in_oam_dma: bool) -> usize {
+        let value = self.mem_read(address);
+        self.apu.get_mut().dmc_fill(value);
+        if in_oam_dma {
+            2
+        } else {
+            4
+        }
+    }
+
+    /// CPU cycles since power on, OAM DMA stalls included
+    pub fn cycles(&self) -> usize {
+        self.cycles
+    }
+
+    /// Copies page `page` ($XX00-$XXFF) to OAM through $2004, starting at the current OAMADDR
+    fn oam_dma(&mut self, page: u8) {
+        let start = (page as u16) << 8;
+        for offset in 0..=0xFF {
+            let value = self.mem_read(start + offset);
+            self.mem_write(PPU_REGISTERS + 4, value);
+        }
+        self.oam_dma_pending = true;
+    }
+
+    /// NMI edge from the PPU, cleared once taken
+    pub fn poll_nmi(&mut self) -> bool {
+        self.ppu.get_mut().poll_nmi()
+    }
+
+    /// State of the CPU /IRQ line, devices hold it asserted until acknowledged
+    pub fn poll_irq(&self) -> bool {
+        let mapper_irq = match &self.mapper {
+            Some(mapper) => mapper.borrow().irq_pending(),
+            None => false,
+        };
+        mapper_irq || self.apu.borrow().irq_pending()
+    }
+
+    /// Controller in port 0 ($4016) or 1 ($4017)
+    pub fn joypad_mut(&mut self, port: usize) -> &mut Joypad {
+        &mut self.joypads.get_mut()[port]
+    }
+
+    pub fn apu(&self) -> Ref<'_, Apu> {
+        self.apu.borrow()
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        self.apu.get_mut()
+    }
+
+    pub fn ppu(&self) -> Ref<'_, Ppu> {
+        self.ppu.borrow()
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        self.ppu.get_mut()
     }
 
     fn get_real_address(&self, address: u16) -> Option<usize> { 
-        let address = match address {
+        match address {
             RAM ..= RAM_MIRRORS_END => Some((address & 0b111_1111_1111) as usize),
-            PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END => {
-                address & 0b0010_0000_0000_0111;
-                todo!("Not implemented")
-            },
             _ => None
-        };
-        address
+        }
     } 
 }
 impl Memory for Bus {
     fn mem_read(&self, address: u16) -> u8 {
-        let real_address = self.get_real_address(address);
-        match real_address {
-            Some(address) => self.cpu_vram[address],
-            None => {
-                println!("Ignore read memory outside CPU memory at: {}", address);
-                0
-            }
+        if let (CARTRIDGE_SPACE ..= CARTRIDGE_SPACE_END, Some(mapper)) = (address, &self.mapper) {
+            return mapper.borrow_mut().cpu_read(address);
+        }
+        if let PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END = address {
+            return self.ppu.borrow_mut().read_register(address & 0b0010_0000_0000_0111);
+        }
+        if address == APU_STATUS {
+            return self.apu.borrow_mut().read_status();
         }
+        if let JOYPAD1 | JOYPAD2 = address {
+            self.joypad_read.set(Some((address - JOYPAD1) as usize));
+            // the upper bits are open bus, usually $40 from the address high byte
+            return 0x40 | self.joypads.borrow_mut()[(address - JOYPAD1) as usize].read();
+        }
+        let real_address = self.get_real_address(address);
+        // nothing answers elsewhere: open bus
+        real_address.map_or(0, |address| self.cpu_vram[address])
         
     }
 
     fn mem_write(&mut self, address: u16, value: u8) {
+        if let (CARTRIDGE_SPACE ..= CARTRIDGE_SPACE_END, Some(mapper)) = (address, &self.mapper) {
+            mapper.borrow_mut().cpu_write(address, value);
+            return;
+        }
+        if let PPU_REGISTERS ..= PPU_REGISTERS_MIRRORS_END = address {
+            if let Some(mapper) = &self.mapper {
+                mapper.borrow_mut().ppu_register_write(address & 0b0010_0000_0000_0111, value);
+            }
+            self.ppu.get_mut().write_register(address & 0b0010_0000_0000_0111, value);
+            return;
+        }
+        if let APU_REGISTERS..=APU_REGISTERS_END | APU_STATUS | APU_FRAME_COUNTER = address {
+            self.apu.get_mut().write_register(address, value);
+            return;
+        }
+        if address == OAM_DMA {
+            self.oam_dma(value);
+            return;
+        }
+        if address == JOYPAD1 {
+            for joypad in self.joypads.get_mut() {
+                joypad.write(value);
+            }
+            return;
+        }
         let real_address = self.get_real_address(address);
         match real_address {
-            Some(address) => {
-                self.cpu_vram[address] = value;
-                println!("Mem write at {:x} - value: {:x}", address, value)
-            },
+            Some(address) => self.cpu_vram[address] = value,
             None => {/* ignore */}
         }
     }
@@ -66,4 +343,77 @@ impl Memory for Bus {
         self.mem_write(address, low);
         self.mem_write(address + 1, high);
     }    
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_oam_dma_copies_page_from_oam_addr() {
+        let mut bus = Bus::new();
+        for i in 0..=0xFF {
+            bus.mem_write(0x0200 + i, i as u8);
+        }
+        bus.mem_write(0x2003, 0x10);
+        bus.mem_write(0x4014, 0x02);
+        let ppu = bus.ppu();
+        assert_eq!(ppu.oam[0x10], 0x00);
+        assert_eq!(ppu.oam[0xFF], 0xEF);
+        assert_eq!(ppu.oam[0x00], 0xF0);
+        assert_eq!(ppu.oam[0x0F], 0xFF);
+    }
+
+    #[test]
+    fn test_ppu_dots_per_cpu_cycle() {
+        let mut bus = Bus::new();
+        bus.tick(5);
+        assert_eq!(bus.ppu().dot(), 15);
+        bus.set_region(Region::Pal);
+        bus.tick(5);
+        assert_eq!(bus.ppu().dot(), 31);
+        bus.set_region(Region::Dendy);
+        bus.tick(5);
+        assert_eq!(bus.ppu().dot(), 46);
+    }
+
+    #[test]
+    fn test_oam_dma_stalls_the_cpu() {
+        let mut bus = Bus::new();
+        bus.mem_write(0x4014, 0x02);
+        bus.tick(4);
+        assert_eq!(bus.cycles(), 4 + 513);
+
+        bus.tick(2);
+        bus.mem_write(0x4014, 0x02);
+        bus.tick(4);
+        assert_eq!(bus.cycles(), 4 + 513 + 2 + 4 + 514);
+    }
+
+    #[test]
+    fn test_dmc_dma_stalls_and_repeats_controller_reads() {
+        use crate::cartridge::test::ines_image;
+        use crate::joypad::JoypadButton;
+
+        let mut bus = Bus::new();
+        bus.load_cartridge(&Cartridge::from_ines(&ines_image(0, 1, 1, 0)).unwrap()).unwrap();
+        bus.joypad_mut(0).set_buttons(JoypadButton::A | JoypadButton::SELECT);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+
+        // a looping 1 byte sample at the fastest rate: the first fetch right away, the next once the output
+        // unit played the 7 silent bits left of its first 8 bit cycle, 54 cycles each
+        bus.mem_write(0x4010, 0x4F);
+        bus.mem_write(0x4015, 0x10);
+        bus.tick(2);
+        assert_eq!(bus.cycles(), 2 + 4);
+        while bus.cycles() < 7 * 54 - 3 {
+            bus.tick(1);
+        }
+
+        // a 4 cycle LDA $4016 whose read cycle the next fetch halts: B is shifted out unseen
+        assert_eq!(bus.mem_read(0x4016), 0x41);
+        bus.tick(4);
+        assert_eq!(bus.cycles(), 7 * 54 + 1 + 4);
+        assert_eq!(bus.mem_read(0x4016), 0x41);
+    }
+}
@@ -0,0 +1,602 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::archive;
+use crate::gamedb;
+use crate::mapper::fds;
+use crate::patch;
+
+const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+const TRAINER_SIZE: usize = 512;
+const UNIF_TAG: [u8; 4] = *b"UNIF";
+const FDS_TAG: [u8; 4] = [0x46, 0x44, 0x53, 0x1A];
+/// Headerless `.fds` images start with the disk info block of the first side
+const FDS_DISK_INFO: &[u8] = b"\x01*NINTENDO-HVC*";
+const FDS_HEADER_SIZE: usize = 16;
+/// iNES mapper number reserved for the disk system
+pub const FDS_MAPPER: u16 = 20;
+const UNIF_HEADER_SIZE: usize = 32;
+
+/// Prefixes of UNIF board names, telling who made the board rather than which board it is
+const UNIF_PREFIXES: [&str; 17] = [
+    "NES-", "HVC-", "UNL-", "BMC-", "BTL-", "IREM-", "KONAMI-", "TENGEN-", "TAITO-", "NAMCO-", "SUNSOFT-",
+    "JALECO-", "BANDAI-", "AVE-", "MLT-", "COLORDREAMS-", "NTDEC-",
+];
+
+/// UNIF board names (without the prefix from `UNIF_PREFIXES`) handled by the existing mappers, with the
+/// matching iNES mapper and submapper numbers
+const UNIF_BOARDS: [(&str, u16, u8); 45] = [
+    ("NROM", 0, 0),
+    ("NROM-128", 0, 0),
+    ("NROM-256", 0, 0),
+    ("RROM", 0, 0),
+    ("SAROM", 1, 0),
+    ("SBROM", 1, 0),
+    ("SCROM", 1, 0),
+    ("SEROM", 1, 5),
+    ("SFROM", 1, 0),
+    ("SGROM", 1, 0),
+    ("SHROM", 1, 5),
+    ("SJROM", 1, 0),
+    ("SKROM", 1, 0),
+    ("SLROM", 1, 0),
+    ("SL1ROM", 1, 0),
+    ("SNROM", 1, 0),
+    ("SOROM", 1, 0),
+    ("SUROM", 1, 0),
+    ("SXROM", 1, 0),
+    ("AMROM", 7, 0),
+    ("ANROM", 7, 0),
+    ("AN1ROM", 7, 0),
+    ("AOROM", 7, 0),
+    ("TBROM", 4, 0),
+    ("TEROM", 4, 0),
+    ("TFROM", 4, 0),
+    ("TGROM", 4, 0),
+    ("TKROM", 4, 0),
+    ("TLROM", 4, 0),
+    ("TNROM", 4, 0),
+    ("TR1ROM", 4, 0),
+    ("TSROM", 4, 0),
+    ("TVROM", 4, 0),
+    ("HKROM", 4, 1),
+    ("TKSROM", 118, 0),
+    ("TLSROM", 118, 0),
+    ("TQROM", 119, 0),
+    ("EKROM", 5, 0),
+    ("ELROM", 5, 0),
+    ("ETROM", 5, 0),
+    ("EWROM", 5, 0),
+    ("PNROM", 9, 0),
+    ("PEEOROM", 9, 0),
+    ("FJROM", 10, 0),
+    ("FKROM", 10, 0),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
+
+impl Mirroring {
+    /// Which 1KB nametable page backs nametable `table` (0 - $2000, 1 - $2400, 2 - $2800, 3 - $2C00).
+    /// Pages 0 and 1 live in the console CIRAM, pages 2 and 3 only exist with four screen VRAM.
+    pub fn nametable_page(&self, table: usize) -> usize {
+        match (self, table & 0b11) {
+            (Mirroring::Horizontal, 0 | 1) => 0,
+            (Mirroring::Horizontal, _) => 1,
+            (Mirroring::Vertical, table) => table & 1,
+            (Mirroring::SingleScreenLower, _) => 0,
+            (Mirroring::SingleScreenUpper, _) => 1,
+            (Mirroring::FourScreen, table) => table,
+        }
+    }
+}
+
+/// CPU/PPU timing the game was made for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy,
+}
+
+/// Timing of each machine type https://www.nesdev.org/wiki/Cycle_reference_chart
+impl Region {
+    /// Master clock in Hz, divided down for the CPU and the PPU
+    pub fn master_clock(&self) -> f64 {
+        match self {
+            Region::Ntsc => 236.25e6 / 11.0,
+            Region::Pal | Region::Dendy => 26.601712e6,
+        }
+    }
+
+    pub fn cpu_clock_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    pub fn ppu_clock_divider(&self) -> u32 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    /// CPU cycles per second
+    pub fn cpu_clock_rate(&self) -> f64 {
+        self.master_clock() / self.cpu_clock_divider() as f64
+    }
+
+    pub fn scanlines_per_frame(&self) -> u16 {
+        match self {
+            Region::Ntsc => 262,
+            Region::Pal | Region::Dendy => 312,
+        }
+    }
+
+    /// Scanline on which the vblank flag gets set; the Dendy keeps NTSC's vblank length and puts its
+    /// extra lines before it
+    pub fn vblank_scanline(&self) -> u16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291,
+        }
+    }
+
+    /// Frames per second the frontend should present
+    pub fn frame_rate(&self) -> f64 {
+        let dots_per_frame = 341.0 * self.scanlines_per_frame() as f64 - if *self == Region::Ntsc { 0.5 } else { 0.0 };
+        self.master_clock() / self.ppu_clock_divider() as f64 / dots_per_frame
+    }
+}
+
+/// Cartridge image as described by its header, independent of the file format it came from.
+pub struct Cartridge {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub region: Region,
+    /// From the UNIF NAME chunk or the game database
+    pub title: Option<String>,
+}
+
+impl Cartridge {
+    /// Reads a ROM file, unpacking it first when it is a zip, gzip or 7z archive (see `archive::extract_rom`
+    /// for `choose_entry`), and applies the patches in order to the in-memory image before parsing it.
+    /// Neither the ROM nor the patch files are modified. Header values of the dumps listed in the game database
+    /// (gamedb.txt) are then corrected from it, and its messages (detected title, overridden values) come back with
+    /// the cartridge for the caller to report.
+    pub fn load(
+        path: &Path,
+        patches: &[PathBuf],
+        choose_entry: &dyn Fn(&[String]) -> Option<usize>,
+    ) -> Result<(Cartridge, Vec<String>), String> {
+        let raw = fs::read(path).map_err(|e| format!("Can not read ROM {}: {}", path.display(), e))?;
+        let mut raw = archive::extract_rom(raw, choose_entry).map_err(|e| format!("{}: {}", path.display(), e))?;
+        for patch_path in patches {
+            let patch_data = fs::read(patch_path)
+                .map_err(|e| format!("Can not read patch {}: {}", patch_path.display(), e))?;
+            raw = patch::apply(&raw, &patch_data).map_err(|e| format!("{}: {}", patch_path.display(), e))?;
+        }
+        let mut cartridge = Cartridge::parse(&raw)?;
+        let messages = gamedb::DATABASE.apply(&mut cartridge);
+        Ok((cartridge, messages))
+    }
+
+    /// Parse an iNES, NES 2.0, UNIF or FDS image, told apart by their magic bytes
+    pub fn parse(raw: &[u8]) -> Result<Cartridge, String> {
+        if raw.starts_with(&UNIF_TAG) {
+            Cartridge::from_unif(raw)
+        } else if raw.starts_with(&FDS_TAG) || raw.starts_with(FDS_DISK_INFO) {
+            Cartridge::from_fds(raw)
+        } else {
+            Cartridge::from_ines(raw)
+        }
+    }
+
+    /// Parse an iNES or NES 2.0 image https://www.nesdev.org/wiki/NES_2.0
+    pub fn from_ines(raw: &[u8]) -> Result<Cartridge, String> {
+        if raw.len() < 16 || raw[0..4] != NES_TAG {
+            return Err("File is not in iNES file format".to_string());
+        }
+
+        let nes2 = raw[7] & 0b0000_1100 == 0b0000_1000;
+        // iNES 1.0 headers with something in bytes 12-15 ("DiskDude!" and other ripper tags) hold garbage from byte 7
+        let mut header = [0; 16];
+        header.copy_from_slice(&raw[..16]);
+        if !nes2 && header[12..] != [0; 4] {
+            header[7..].fill(0);
+        }
+        let mut mapper = ((header[7] & 0b1111_0000) | (header[6] >> 4)) as u16;
+        let mut submapper = 0;
+
+        let four_screen = header[6] & 0b1000 != 0;
+        let vertical_mirroring = header[6] & 0b1 != 0;
+        let mirroring = match (four_screen, vertical_mirroring) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+        let battery = header[6] & 0b10 != 0;
+        let mut region = if header[9] & 0b1 != 0 { Region::Pal } else { Region::Ntsc };
+
+        let mut prg_rom_size = header[4] as usize * PRG_ROM_PAGE_SIZE;
+        let mut chr_rom_size = header[5] as usize * CHR_ROM_PAGE_SIZE;
+        let (prg_ram_size, prg_nvram_size, chr_ram_size, chr_nvram_size);
+
+        if nes2 {
+            mapper |= ((header[8] & 0b1111) as u16) << 8;
+            submapper = header[8] >> 4;
+            prg_rom_size = nes2_rom_size(header[4], header[9] & 0b1111, PRG_ROM_PAGE_SIZE)?;
+            chr_rom_size = nes2_rom_size(header[5], header[9] >> 4, CHR_ROM_PAGE_SIZE)?;
+            prg_ram_size = nes2_ram_size(header[10] & 0b1111);
+            prg_nvram_size = nes2_ram_size(header[10] >> 4);
+            chr_ram_size = nes2_ram_size(header[11] & 0b1111);
+            chr_nvram_size = nes2_ram_size(header[11] >> 4);
+            // multi-region games (2) run fine as NTSC
+            region = match header[12] & 0b11 {
+                1 => Region::Pal,
+                3 => Region::Dendy,
+                _ => Region::Ntsc,
+            };
+        } else {
+            // iNES 1.0 only tells the PRG RAM size (in 8KB units, 0 infers 8KB) and whether it is battery backed
+            let ram_size = std::cmp::max(header[8] as usize, 1) * 0x2000;
+            prg_ram_size = if battery { 0 } else { ram_size };
+            prg_nvram_size = if battery { ram_size } else { 0 };
+            chr_ram_size = if chr_rom_size == 0 { 0x2000 } else { 0 };
+            chr_nvram_size = 0;
+        }
+
+        if prg_rom_size == 0 {
+            return Err("iNES image has no PRG ROM".to_string());
+        }
+
+        let skip_trainer = header[6] & 0b100 != 0;
+        let prg_rom_start = 16 + if skip_trainer { TRAINER_SIZE } else { 0 };
+        let chr_rom_start = prg_rom_start.checked_add(prg_rom_size).ok_or("ROM size out of range")?;
+        let chr_rom_end = chr_rom_start.checked_add(chr_rom_size).ok_or("ROM size out of range")?;
+
+        if raw.len() < chr_rom_end {
+            return Err(format!(
+                "ROM image is truncated: expected {} bytes, got {}",
+                chr_rom_end,
+                raw.len()
+            ));
+        }
+
+        Ok(Cartridge {
+            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: raw[chr_rom_start..chr_rom_end].to_vec(),
+            mapper,
+            submapper,
+            mirroring,
+            battery,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            region,
+            title: None,
+        })
+    }
+
+    /// Parse a UNIF image https://www.nesdev.org/wiki/UNIF
+    pub fn from_unif(raw: &[u8]) -> Result<Cartridge, String> {
+        if raw.len() < UNIF_HEADER_SIZE || raw[0..4] != UNIF_TAG {
+            return Err("File is not in UNIF file format".to_string());
+        }
+
+        let mut board = None;
+        let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+        let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+        let mut mirroring = Mirroring::Horizontal;
+        let mut battery = false;
+        let mut title = None;
+        let mut region = Region::Ntsc;
+
+        let mut position = UNIF_HEADER_SIZE;
+        while position < raw.len() {
+            if raw.len() < position + 8 {
+                return Err("UNIF chunk header is truncated".to_string());
+            }
+            let id = &raw[position..position + 4];
+            let len = u32::from_le_bytes([raw[position + 4], raw[position + 5], raw[position + 6], raw[position + 7]]);
+            let data = raw
+                .get(position + 8..position + 8 + len as usize)
+                .ok_or_else(|| format!("UNIF chunk {} is truncated", String::from_utf8_lossy(id)))?;
+            position += 8 + len as usize;
+
+            match id {
+                b"MAPR" => board = Some(unif_string(data)),
+                b"NAME" => title = Some(unif_string(data)),
+                b"BATR" => battery = true,
+                // 5 means the mapper controls mirroring, it starts out as whatever the mapper resets to
+                b"MIRR" => {
+                    mirroring = match data.first() {
+                        Some(1) => Mirroring::Vertical,
+                        Some(2) => Mirroring::SingleScreenLower,
+                        Some(3) => Mirroring::SingleScreenUpper,
+                        Some(4) => Mirroring::FourScreen,
+                        _ => Mirroring::Horizontal,
+                    }
+                }
+                // 2 means the game runs on both, NTSC is used then
+                b"TVCI" if data.first() == Some(&1) => region = Region::Pal,
+                [b'P', b'R', b'G', index] | [b'C', b'H', b'R', index] => {
+                    let index = (*index as char)
+                        .to_digit(16)
+                        .ok_or_else(|| format!("Bad UNIF chunk {}", String::from_utf8_lossy(id)))?;
+                    let chunks = if id.starts_with(b"PRG") { &mut prg_chunks } else { &mut chr_chunks };
+                    chunks[index as usize] = Some(data);
+                }
+                _ => {}
+            }
+        }
+
+        let board = board.ok_or("UNIF image has no MAPR chunk")?;
+        let name = UNIF_PREFIXES
+            .iter()
+            .find_map(|prefix| board.strip_prefix(prefix))
+            .unwrap_or(&board);
+        let (_, mapper, submapper) = UNIF_BOARDS
+            .iter()
+            .find(|(known, _, _)| *known == name)
+            .ok_or_else(|| format!("UNIF board {} is not supported", board))?;
+
+        let prg_rom: Vec<u8> = prg_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+        let chr_rom: Vec<u8> = chr_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+        if prg_rom.is_empty() {
+            return Err("UNIF image has no PRG ROM".to_string());
+        }
+
+        Ok(Cartridge {
+            chr_ram_size: if chr_rom.is_empty() { 0x2000 } else { 0 },
+            prg_rom,
+            chr_rom,
+            mapper: *mapper,
+            submapper: *submapper,
+            mirroring,
+            battery,
+            prg_ram_size: if battery { 0 } else { 0x2000 },
+            prg_nvram_size: if battery { 0x2000 } else { 0 },
+            chr_nvram_size: 0,
+            region,
+            title,
+        })
+    }
+
+    /// Parse a Famicom Disk System image, with or without the fwNES header https://www.nesdev.org/wiki/FDS_file_format
+    ///
+    /// The disk sides end up in `prg_rom` and the disk system BIOS has to be supplied separately
+    /// (see `Bus::load_fds`). Disks are writable, so they count as battery backed and changes go to the save file.
+    pub fn from_fds(raw: &[u8]) -> Result<Cartridge, String> {
+        let disk = if raw.starts_with(&FDS_TAG) { &raw[std::cmp::min(FDS_HEADER_SIZE, raw.len())..] } else { raw };
+        if !disk.starts_with(FDS_DISK_INFO) {
+            return Err("File is not in FDS file format".to_string());
+        }
+        let sides = disk.len() / fds::SIDE_SIZE;
+        if sides == 0 {
+            return Err(format!("FDS image is truncated: expected {} bytes, got {}", fds::SIDE_SIZE, disk.len()));
+        }
+
+        Ok(Cartridge {
+            prg_rom: disk[..sides * fds::SIDE_SIZE].to_vec(),
+            chr_rom: vec![],
+            mapper: FDS_MAPPER,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: true,
+            prg_ram_size: 0x8000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0x2000,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        })
+    }
+}
+
+/// UNIF strings are null terminated UTF-8
+fn unif_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&byte| byte == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).to_string()
+}
+
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> Result<usize, String> {
+    if msb == 0b1111 {
+        // exponent-multiplier notation: 2^E * (MM * 2 + 1), E going up to 63
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        1usize
+            .checked_shl(exponent)
+            .and_then(|size| size.checked_mul(multiplier))
+            .ok_or_else(|| "ROM size out of range".to_string())
+    } else {
+        Ok(((msb as usize) << 8 | lsb as usize) * page_size)
+    }
+}
+
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    pub fn ines_image(mapper: u8, prg_banks: u8, chr_banks: u8, flags_6: u8) -> Vec<u8> {
+        let mut raw = vec![
+            0x4E, 0x45, 0x53, 0x1A,
+            prg_banks,
+            chr_banks,
+            (mapper << 4) | flags_6,
+            mapper & 0xF0,
+            0, 0, 0, 0, 0, 0, 0, 0,
+        ];
+        raw.extend(vec![0; prg_banks as usize * PRG_ROM_PAGE_SIZE + chr_banks as usize * CHR_ROM_PAGE_SIZE]);
+        raw
+    }
+
+    #[test]
+    fn test_ines_header() {
+        let cartridge = Cartridge::from_ines(&ines_image(4, 2, 1, 0b11)).unwrap();
+        assert_eq!(cartridge.mapper, 4);
+        assert_eq!(cartridge.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!(cartridge.chr_rom.len(), CHR_ROM_PAGE_SIZE);
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert!(cartridge.battery);
+        assert_eq!(cartridge.prg_nvram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_nes2_header() {
+        let mut raw = ines_image(4, 2, 0, 0);
+        raw[7] |= 0b1000;
+        raw[8] = 0x10;
+        raw[10] = 0x07;
+        raw[11] = 0x07;
+        raw[12] = 0x03;
+        let cartridge = Cartridge::from_ines(&raw).unwrap();
+        assert_eq!(cartridge.submapper, 1);
+        assert_eq!(cartridge.region, Region::Dendy);
+        assert_eq!(cartridge.prg_ram_size, 0x2000);
+        assert_eq!(cartridge.chr_ram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_ignores_diskdude_header_tail() {
+        let mut raw = ines_image(4, 2, 1, 0);
+        raw[7..16].copy_from_slice(b"DiskDude!");
+        let cartridge = Cartridge::from_ines(&raw).unwrap();
+        assert_eq!(cartridge.mapper, 4);
+        assert_eq!(cartridge.region, Region::Ntsc);
+        assert_eq!(cartridge.prg_ram_size, 0x2000);
+    }
+
+    #[test]
+    fn test_rejects_empty_prg_rom() {
+        assert_eq!(Cartridge::from_ines(&ines_image(9, 0, 1, 0)).err().unwrap(), "iNES image has no PRG ROM");
+    }
+
+    #[test]
+    fn test_nes2_rom_size_out_of_range() {
+        // 2^63 bytes of PRG ROM, then 2^63 * 7
+        let mut raw = ines_image(0, 0, 0, 0);
+        raw[7] |= 0b1000;
+        raw[4] = 0xFC;
+        raw[9] = 0x0F;
+        assert!(Cartridge::from_ines(&raw).is_err());
+        raw[4] = 0xFF;
+        assert_eq!(Cartridge::from_ines(&raw).err().unwrap(), "ROM size out of range");
+        // 2^63 bytes of both PRG and CHR ROM
+        raw[4] = 0xFC;
+        raw[5] = 0xFC;
+        raw[9] = 0xFF;
+        assert_eq!(Cartridge::from_ines(&raw).err().unwrap(), "ROM size out of range");
+    }
+
+    fn unif_chunk(id: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    #[test]
+    fn test_unif() {
+        let mut raw = b"UNIF".to_vec();
+        raw.extend(7u32.to_le_bytes());
+        raw.extend([0; 24]);
+        raw.extend(unif_chunk(b"MAPR", b"NES-TLROM\0"));
+        raw.extend(unif_chunk(b"NAME", b"Some Game\0"));
+        raw.extend(unif_chunk(b"PRG1", &[2; PRG_ROM_PAGE_SIZE]));
+        raw.extend(unif_chunk(b"PRG0", &[1; PRG_ROM_PAGE_SIZE]));
+        raw.extend(unif_chunk(b"CHR0", &[3; CHR_ROM_PAGE_SIZE]));
+        raw.extend(unif_chunk(b"MIRR", &[1]));
+        raw.extend(unif_chunk(b"BATR", &[1]));
+        raw.extend(unif_chunk(b"TVCI", &[1]));
+
+        let cartridge = Cartridge::parse(&raw).unwrap();
+        assert_eq!(cartridge.mapper, 4);
+        assert_eq!(cartridge.title.as_deref(), Some("Some Game"));
+        assert_eq!(cartridge.prg_rom.len(), 2 * PRG_ROM_PAGE_SIZE);
+        assert_eq!((cartridge.prg_rom[0], cartridge.prg_rom[PRG_ROM_PAGE_SIZE]), (1, 2));
+        assert_eq!(cartridge.chr_rom, vec![3; CHR_ROM_PAGE_SIZE]);
+        assert_eq!(cartridge.mirroring, Mirroring::Vertical);
+        assert_eq!(cartridge.prg_nvram_size, 0x2000);
+        assert_eq!(cartridge.region, Region::Pal);
+
+        raw[32 + 8..32 + 8 + 9].copy_from_slice(b"UNL-XXXXX");
+        assert_eq!(Cartridge::parse(&raw).err().unwrap(), "UNIF board UNL-XXXXX is not supported");
+    }
+
+    #[test]
+    fn test_unif_board_prefixes() {
+        let unif = |board: &[u8]| {
+            let mut raw = b"UNIF".to_vec();
+            raw.extend(7u32.to_le_bytes());
+            raw.extend([0; 24]);
+            raw.extend(unif_chunk(b"MAPR", board));
+            raw.extend(unif_chunk(b"PRG0", &[1; PRG_ROM_PAGE_SIZE]));
+            Cartridge::parse(&raw)
+        };
+        assert_eq!(unif(b"NROM-256\0").unwrap().mapper, 0);
+        assert_eq!(unif(b"NES-NROM-256\0").unwrap().mapper, 0);
+        assert_eq!(unif(b"HVC-TLROM\0").unwrap().mapper, 4);
+        assert_eq!(unif(b"NES-SNROM\0").unwrap().mapper, 1);
+        assert_eq!(unif(b"NES-AOROM\0").unwrap().mapper, 7);
+        // only a known prefix is dropped
+        assert!(unif(b"XYZ-TLROM\0").is_err());
+    }
+
+    #[test]
+    fn test_fds() {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(fds::SIDE_SIZE, 0);
+        let mut raw = vec![0x46, 0x44, 0x53, 0x1A, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        raw.extend(&side);
+        raw.extend(&side);
+
+        let cartridge = Cartridge::parse(&raw).unwrap();
+        assert_eq!(cartridge.mapper, FDS_MAPPER);
+        assert_eq!(cartridge.prg_rom.len(), 2 * fds::SIDE_SIZE);
+        assert_eq!(Cartridge::parse(&side).unwrap().prg_rom, side);
+        assert!(Cartridge::from_fds(&raw[..1000]).is_err());
+    }
+
+    #[test]
+    fn test_region_timing() {
+        assert_eq!(Region::Ntsc.cpu_clock_rate().round(), 1_789_773.0);
+        assert_eq!(Region::Pal.cpu_clock_rate().round(), 1_662_607.0);
+        assert_eq!(Region::Dendy.cpu_clock_rate().round(), 1_773_447.0);
+        assert!((Region::Ntsc.frame_rate() - 60.0988).abs() < 0.001);
+        assert!((Region::Pal.frame_rate() - 50.007).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_rejects_bad_tag() {
+        assert!(Cartridge::from_ines(&[0; 32]).is_err());
+    }
+}
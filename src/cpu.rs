@@ -42,6 +42,41 @@ bitflags! {
 
 const STACK: u16 = 0x100;
 const STACK_RESET: u8 = 0xfd;
+
+/// The reads marked "+1 if page crossed" in the opcode table: ADC, SBC, AND, EOR, ORA, CMP and LDA through
+/// Absolute,X, Absolute,Y and (Indirect),Y, LDX Absolute,Y and LDY Absolute,X
+const PAGE_CROSSING_READS: [u8; 23] = [
+    0x7d, 0x79, 0x71, 0xfd, 0xf9, 0xf1, 0x3d, 0x39, 0x31, 0x5d, 0x59, 0x51, 0x1d, 0x19, 0x11, 0xdd, 0xd9, 0xd1,
+    0xbd, 0xb9, 0xb1, 0xbe, 0xbc,
+];
+
+mod interrupt {
+    pub(super) struct Interrupt {
+        pub(super) vector_addr: u16,
+        pub(super) b_flag_mask: u8,
+        pub(super) cpu_cycles: u8,
+    }
+
+    pub(super) const NMI: Interrupt = Interrupt {
+        vector_addr: 0xfffa,
+        b_flag_mask: 0b0010_0000,
+        cpu_cycles: 7,
+    };
+
+    pub(super) const IRQ: Interrupt = Interrupt {
+        vector_addr: 0xfffe,
+        b_flag_mask: 0b0010_0000,
+        cpu_cycles: 7,
+    };
+
+    /// The 7 cycles of BRK come from its opcode
+    pub(super) const BRK: Interrupt = Interrupt {
+        vector_addr: 0xfffe,
+        b_flag_mask: 0b0011_0000,
+        cpu_cycles: 0,
+    };
+}
+
 pub struct Cpu {
     pub program_counter: u16,
     register_a: u8,
@@ -65,7 +100,29 @@ impl Cpu {
         self.stack_pointer = STACK_RESET;
         self.flags = CpuFlags::from_bits_truncate(0b100100);
         self.program_counter = self.bus.mem_read_u16(0xfffc);
-    } 
+        // the reset sequence takes as long as an interrupt
+        self.bus.tick(7);
+    }
+
+    pub fn register_a(&self) -> u8 {
+        self.register_a
+    }
+
+    pub fn register_x(&self) -> u8 {
+        self.register_x
+    }
+
+    pub fn register_y(&self) -> u8 {
+        self.register_y
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn flags(&self) -> CpuFlags {
+        self.flags
+    }
 
     fn update_zero_and_negative_flags(&mut self, result: u8) {
         if result == 0 {
@@ -125,6 +182,22 @@ impl Cpu {
             AddressingMode::NoneAddressing => panic!("Do not support this addressing mode")
         }
     }
+
+    /// Whether the index carries into the high byte of the address, costing the reads a cycle
+    fn page_crossed(&self, address_mode: &AddressingMode) -> bool {
+        let (base, index) = match address_mode {
+            AddressingMode::AbsoluteX => (self.bus.mem_read_u16(self.program_counter), self.register_x),
+            AddressingMode::AbsoluteY => (self.bus.mem_read_u16(self.program_counter), self.register_y),
+            AddressingMode::IndirectY => {
+                let base = self.bus.mem_read(self.program_counter);
+                let lo = self.bus.mem_read(base as u16);
+                let hi = self.bus.mem_read(base.wrapping_add(1) as u16);
+                ((hi as u16) << 8 | (lo as u16), self.register_y)
+            }
+            _ => return false,
+        };
+        base & 0xFF00 != base.wrapping_add(index as u16) & 0xFF00
+    }
     
     fn stack_pop(&mut self) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
@@ -160,6 +233,20 @@ impl Cpu {
         self.update_zero_and_negative_flags(to_value.wrapping_sub(param))
     }
 
+    // https://www.nesdev.org/wiki/CPU_interrupts
+    fn interrupt(&mut self, interrupt: interrupt::Interrupt) {
+        self.stack_push_u16(self.program_counter);
+        let mut flags = self.flags;
+        flags.remove(CpuFlags::BREAK);
+        flags.remove(CpuFlags::BREAK2);
+        flags.bits |= interrupt.b_flag_mask;
+        self.stack_push(flags.bits());
+        self.flags.insert(CpuFlags::INTERRUPT_DISABLE);
+
+        self.bus.tick(interrupt.cpu_cycles);
+        self.program_counter = self.bus.mem_read_u16(interrupt.vector_addr);
+    }
+
     pub fn load(&mut self, program: &Vec<u8>) {
         // self.memory[0x8000 ..].copy_from_slice(&program);
         // self.program_counter = 0x8000;
@@ -173,302 +260,332 @@ impl Cpu {
 
     fn load_and_run(&mut self, program: &Vec<u8>) {
         self.load(program);
-        self.run_with_callback(|arg| {})
+        self.run_with_callback(|arg| {}).unwrap()
     }
 
-    pub fn run_with_callback<F>(&mut self, mut callback: F) 
+    /// Runs until the next instruction is a BRK, which ends the test programs
+    pub fn run_with_callback<F>(&mut self, mut callback: F) -> Result<(), String>
     where F: FnMut(&mut Self) {
+        while self.bus.peek(self.program_counter) != Some(0x00) {
+            self.step()?;
+            callback(self);
+        }
+        Ok(())
+    }
+
+    /// Runs one instruction, serving a pending interrupt first. Unknown opcodes are an error, with the program
+    /// counter left on them.
+    pub fn step(&mut self) -> Result<(), String> {
         let ref opcodes: HashMap<u8, &'static opscode::OpCode> = *opscode::OPCODES_MAP;
-        loop {
-            let code = self.bus.mem_read(self.program_counter);
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
-            let opcode = opcodes.get( &code).expect(&format!("OpCode {:x} is not regconized", code));
-            println!("opcode: {:x}\tregister_a: {:x}\tregister_x: {:x}\t register_y: {:x}\tpc: {:x}, sp: {:x}, flag: {:#8b}", code, self.register_a, self.register_x, self.register_y, self.program_counter, self.stack_pointer, self.flags.bits);
-            match code {
-                0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
-                    self.lda(&opcode.mode);
-                }
+        if self.bus.poll_nmi() {
+            self.interrupt(interrupt::NMI);
+        } else if self.bus.poll_irq() && !self.flags.contains(CpuFlags::INTERRUPT_DISABLE) {
+            self.interrupt(interrupt::IRQ);
+        }
 
-                0xAA => self.tax(),
-                0xe8 => self.inx(),
-                0x00 => return,
+        let code = self.bus.mem_read(self.program_counter);
+        let opcode = opcodes
+            .get(&code)
+            .ok_or_else(|| format!("Unknown opcode ${:02X} at ${:04X}", code, self.program_counter))?;
+        self.program_counter += 1;
+        let program_counter_state = self.program_counter;
+        if PAGE_CROSSING_READS.contains(&code) && self.page_crossed(&opcode.mode) {
+            self.bus.tick(1);
+        }
+        match code {
+            0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
+                self.lda(&opcode.mode);
+            }
 
-                /* CLD */ 0xd8 => self.cld(),
+            0xAA => self.tax(),
+            0xe8 => self.inx(),
+            /* BRK, its second byte is padding */
+            0x00 => {
+                self.program_counter += 1;
+                self.interrupt(interrupt::BRK);
+            }
 
-                /* CLI */ 0x58 => self.cli(),
+            /* CLD */ 0xd8 => self.cld(),
 
-                /* CLV */ 0xb8 => self.clv(),
+            /* CLI */ 0x58 => self.cli(),
 
-                /* CLC */ 0x18 => self.clc(),
+            /* CLV */ 0xb8 => self.clv(),
 
-                /* SEC */ 0x38 => self.sec(),
+            /* CLC */ 0x18 => self.clc(),
 
-                /* SEI */ 0x78 => self.sei(),
+            /* SEC */ 0x38 => self.sec(),
 
-                /* SED */ 0xf8 => self.sed(),
+            /* SEI */ 0x78 => self.sei(),
 
-                /* PHA */ 0x48 => self.pha(),
+            /* SED */ 0xf8 => self.sed(),
 
-                /* PLA */
-                0x68 => {
-                    self.pla();
-                }
+            /* PHA */ 0x48 => self.pha(),
 
-                /* PHP */
-                0x08 => {
-                    self.php();
-                }
+            /* PLA */
+            0x68 => {
+                self.pla();
+            }
 
-                /* PLP */
-                0x28 => {
-                    self.plp();
-                }
-
-                /* ADC */
-                0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => {
-                    self.adc(&opcode.mode);
-                }
-
-                /* SBC */
-                0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => {
-                    self.sbc(&opcode.mode);
-                }
-
-                /* AND */
-                0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => {
-                    self.and(&opcode.mode);
-                }
-
-                /* EOR */
-                0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => {
-                    self.eor(&opcode.mode);
-                }
-
-                /* ORA */
-                0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => {
-                    self.ora(&opcode.mode);
-                }
-
-                /* LSR */ 0x4a => self.lsr_accumulator(),
-
-                /* LSR */
-                0x46 | 0x56 | 0x4e | 0x5e => {
-                    self.lsr(&opcode.mode);
-                }
-
-                /*ASL*/ 0x0a => self.asl_accumulator(),
-
-                /* ASL */
-                0x06 | 0x16 | 0x0e | 0x1e => {
-                    self.asl(&opcode.mode);
-                }
-
-                /*ROL*/ 0x2a => self.rol_accumulator(),
-
-                /* ROL */
-                0x26 | 0x36 | 0x2e | 0x3e => {
-                    self.rol(&opcode.mode);
-                }
-
-                /* ROR */ 0x6a => self.ror_accumulator(),
-
-                /* ROR */
-                0x66 | 0x76 | 0x6e | 0x7e => {
-                    self.ror(&opcode.mode);
-                }
-
-                /* INC */
-                0xe6 | 0xf6 | 0xee | 0xfe => {
-                    self.inc(&opcode.mode);
-                }
-
-                /* INY */
-                0xc8 => self.iny(),
-
-                /* DEC */
-                0xc6 | 0xd6 | 0xce | 0xde => {
-                    self.dec(&opcode.mode);
-                }
-
-                /* DEX */
-                0xca => {
-                    self.dex();
-                }
-
-                /* DEY */
-                0x88 => {
-                    self.dey();
-                }
-
-                /* CMP */
-                0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => {
-                    self.compare(&opcode.mode, self.register_a);
-                }
-
-                /* CPY */
-                0xc0 | 0xc4 | 0xcc => {
-                    self.compare(&opcode.mode, self.register_y);
-                }
-
-                /* CPX */
-                0xe0 | 0xe4 | 0xec => self.compare(&opcode.mode, self.register_x),
-
-                /* JMP Absolute */
-                0x4c => {
-                    let mem_address = self.bus.mem_read_u16(self.program_counter);
-                    self.program_counter = mem_address;
-                }
-
-                /* JMP Indirect */
-                0x6c => {
-                    let mem_address = self.bus.mem_read_u16(self.program_counter);
-                    // let indirect_ref = self.mem_read_u16(mem_address);
-                    //6502 bug mode with with page boundary:
-                    //  if address $3000 contains $40, $30FF contains $80, and $3100 contains $50,
-                    // the result of JMP ($30FF) will be a transfer of control to $4080 rather than $5080 as you intended
-                    // i.e. the 6502 took the low byte of the address from $30FF and the high byte from $3000
-
-                    let indirect_ref = if mem_address & 0x00FF == 0x00FF {
-                        let lo = self.bus.mem_read(mem_address);
-                        let hi = self.bus.mem_read(mem_address & 0xFF00);
-                        (hi as u16) << 8 | (lo as u16)
-                    } else {
-                        self.bus.mem_read_u16(mem_address)
-                    };
-
-                    self.program_counter = indirect_ref;
-                }
-
-                /* JSR */
-                0x20 => {
-                    self.stack_push_u16(self.program_counter + 2 - 1);
-                    let target_address = self.bus.mem_read_u16(self.program_counter);
-                    self.program_counter = target_address
-                }
-
-                /* RTS */
-                0x60 => {
-                    self.program_counter = self.stack_pop_u16() + 1;
-                }
-
-                /* RTI */
-                0x40 => {
-                    self.flags.bits = self.stack_pop();
-                    self.flags.remove(CpuFlags::BREAK);
-                    self.flags.insert(CpuFlags::BREAK2);
-
-                    self.program_counter = self.stack_pop_u16();
-                }
-
-                /* BNE */
-                0xd0 => {
-                    self.bne();
-                }
-
-                /* BVS */
-                0x70 => {
-                    self.bvs();
-                }
-
-                /* BVC */
-                0x50 => {
-                    self.bvc();
-                }
-
-                /* BPL */
-                0x10 => {
-                    self.bpl();
-                }
-
-                /* BMI */
-                0x30 => {
-                    self.bmi();
-                }
-
-                /* BEQ */
-                0xf0 => {
-                    self.beq();
-                }
-
-                /* BCS */
-                0xb0 => {
-                    self.bcs();
-                }
-
-                /* BCC */
-                0x90 => {
-                    self.bcc();
-                }
-
-                /* BIT */
-                0x24 | 0x2c => {
-                    self.bit(&opcode.mode);
-                }
-
-                /* STA */
-                0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
-                    self.sta(&opcode.mode);
-                }
-
-                /* STX */
-                0x86 | 0x96 | 0x8e => {
-                    self.stx(&opcode.mode)
-                }
-
-                /* STY */
-                0x84 | 0x94 | 0x8c => {
-                    self.sty(&opcode.mode)
-                }
-
-                /* LDX */
-                0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => {
-                    self.ldx(&opcode.mode);
-                }
-
-                /* LDY */
-                0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => {
-                    self.ldy(&opcode.mode);
-                }
-
-                /* NOP */
-                0xea => {
-                    //do nothing
-                }
-
-                /* TAY */
-                0xa8 => {
-                    self.tay();
-                }
-
-                /* TSX */
-                0xba => {
-                    self.tsx();
-                }
-
-                /* TXA */
-                0x8a => {
-                    self.txa();
-                }
-
-                /* TXS */
-                0x9a => {
-                    self.txs();
-                }
-
-                /* TYA */
-                0x98 => {
-                    self.tya();
-                }
-
-                _ => todo!(),
-            }
-            if program_counter_state == self.program_counter {
-                self.program_counter += (opcode.len - 1) as u16;
+            /* PHP */
+            0x08 => {
+                self.php();
             }
 
-            callback(self);
+            /* PLP */
+            0x28 => {
+                self.plp();
+            }
+
+            /* ADC */
+            0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => {
+                self.adc(&opcode.mode);
+            }
+
+            /* SBC */
+            0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => {
+                self.sbc(&opcode.mode);
+            }
+
+            /* AND */
+            0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => {
+                self.and(&opcode.mode);
+            }
+
+            /* EOR */
+            0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => {
+                self.eor(&opcode.mode);
+            }
+
+            /* ORA */
+            0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => {
+                self.ora(&opcode.mode);
+            }
+
+            /* LSR */ 0x4a => self.lsr_accumulator(),
+
+            /* LSR */
+            0x46 | 0x56 | 0x4e | 0x5e => {
+                self.lsr(&opcode.mode);
+            }
+
+            /*ASL*/ 0x0a => self.asl_accumulator(),
+
+            /* ASL */
+            0x06 | 0x16 | 0x0e | 0x1e => {
+                self.asl(&opcode.mode);
+            }
+
+            /*ROL*/ 0x2a => self.rol_accumulator(),
+
+            /* ROL */
+            0x26 | 0x36 | 0x2e | 0x3e => {
+                self.rol(&opcode.mode);
+            }
+
+            /* ROR */ 0x6a => self.ror_accumulator(),
+
+            /* ROR */
+            0x66 | 0x76 | 0x6e | 0x7e => {
+                self.ror(&opcode.mode);
+            }
+
+            /* INC */
+            0xe6 | 0xf6 | 0xee | 0xfe => {
+                self.inc(&opcode.mode);
+            }
+
+            /* INY */
+            0xc8 => self.iny(),
+
+            /* DEC */
+            0xc6 | 0xd6 | 0xce | 0xde => {
+                self.dec(&opcode.mode);
+            }
+
+            /* DEX */
+            0xca => {
+                self.dex();
+            }
+
+            /* DEY */
+            0x88 => {
+                self.dey();
+            }
+
+            /* CMP */
+            0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => {
+                self.compare(&opcode.mode, self.register_a);
+            }
+
+            /* CPY */
+            0xc0 | 0xc4 | 0xcc => {
+                self.compare(&opcode.mode, self.register_y);
+            }
+
+            /* CPX */
+            0xe0 | 0xe4 | 0xec => self.compare(&opcode.mode, self.register_x),
+
+            /* JMP Absolute */
+            0x4c => {
+                let mem_address = self.bus.mem_read_u16(self.program_counter);
+                self.program_counter = mem_address;
+            }
+
+            /* JMP Indirect */
+            0x6c => {
+                let mem_address = self.bus.mem_read_u16(self.program_counter);
+                // let indirect_ref = self.mem_read_u16(mem_address);
+                //6502 bug mode with with page boundary:
+                //  if address $3000 contains $40, $30FF contains $80, and $3100 contains $50,
+                // the result of JMP ($30FF) will be a transfer of control to $4080 rather than $5080 as you intended
+                // i.e. the 6502 took the low byte of the address from $30FF and the high byte from $3000
+
+                let indirect_ref = if mem_address & 0x00FF == 0x00FF {
+                    let lo = self.bus.mem_read(mem_address);
+                    let hi = self.bus.mem_read(mem_address & 0xFF00);
+                    (hi as u16) << 8 | (lo as u16)
+                } else {
+                    self.bus.mem_read_u16(mem_address)
+                };
+
+                self.program_counter = indirect_ref;
+            }
+
+            /* JSR */
+            0x20 => {
+                self.stack_push_u16(self.program_counter + 2 - 1);
+                let target_address = self.bus.mem_read_u16(self.program_counter);
+                self.program_counter = target_address
+            }
+
+            /* RTS */
+            0x60 => {
+                self.program_counter = self.stack_pop_u16() + 1;
+            }
+
+            /* RTI */
+            0x40 => {
+                self.flags.bits = self.stack_pop();
+                self.flags.remove(CpuFlags::BREAK);
+                self.flags.insert(CpuFlags::BREAK2);
+
+                self.program_counter = self.stack_pop_u16();
+            }
+
+            /* BNE */
+            0xd0 => {
+                self.bne();
+            }
+
+            /* BVS */
+            0x70 => {
+                self.bvs();
+            }
+
+            /* BVC */
+            0x50 => {
+                self.bvc();
+            }
+
+            /* BPL */
+            0x10 => {
+                self.bpl();
+            }
+
+            /* BMI */
+            0x30 => {
+                self.bmi();
+            }
+
+            /* BEQ */
+            0xf0 => {
+                self.beq();
+            }
+
+            /* BCS */
+            0xb0 => {
+                self.bcs();
+            }
+
+            /* BCC */
+            0x90 => {
+                self.bcc();
+            }
+
+            /* BIT */
+            0x24 | 0x2c => {
+                self.bit(&opcode.mode);
+            }
+
+            /* STA */
+            0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => {
+                self.sta(&opcode.mode);
+            }
+
+            /* STX */
+            0x86 | 0x96 | 0x8e => {
+                self.stx(&opcode.mode)
+            }
+
+            /* STY */
+            0x84 | 0x94 | 0x8c => {
+                self.sty(&opcode.mode)
+            }
+
+            /* LDX */
+            0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => {
+                self.ldx(&opcode.mode);
+            }
+
+            /* LDY */
+            0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => {
+                self.ldy(&opcode.mode);
+            }
+
+            /* NOP */
+            0xea => {
+                //do nothing
+            }
+
+            /* TAY */
+            0xa8 => {
+                self.tay();
+            }
+
+            /* TSX */
+            0xba => {
+                self.tsx();
+            }
+
+            /* TXA */
+            0x8a => {
+                self.txa();
+            }
+
+            /* TXS */
+            0x9a => {
+                self.txs();
+            }
+
+            /* TYA */
+            0x98 => {
+                self.tya();
+            }
+
+            _ => {
+                self.program_counter -= 1;
+                return Err(format!(
+                    "Opcode ${:02X} ({}) at ${:04X} is not implemented",
+                    code, opcode.mnemonic, self.program_counter
+                ));
+            }
         }
+        if program_counter_state == self.program_counter {
+            self.program_counter += (opcode.len - 1) as u16;
+        }
+
+        self.bus.tick(opcode.cycles);
+        Ok(())
     }
 }
 
@@ -509,25 +626,27 @@ fn asl(&mut self, address_mode: &AddressingMode) -> u8 {
     }
 
 
-    fn beq(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if self.flags.contains(CpuFlags::ZERO) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
+    /// Taken branches cost a cycle, and one more when they land on another page
+    fn branch(&mut self, condition: bool) {
+        if condition {
+            let param = self.bus.mem_read(self.program_counter) as i8;
+            let next = self.program_counter.wrapping_add(1);
+            let target = next.wrapping_add(param as u16);
+            self.bus.tick(if next & 0xFF00 == target & 0xFF00 { 1 } else { 2 });
+            self.program_counter = target
         }
     }
 
+    fn beq(&mut self) {
+        self.branch(self.flags.contains(CpuFlags::ZERO))
+    }
+
     fn bcc(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if !self.flags.contains(CpuFlags::CARRY) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
-        }
+        self.branch(!self.flags.contains(CpuFlags::CARRY))
     }
 
     fn bcs(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if self.flags.contains(CpuFlags::CARRY) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
-        }
+        self.branch(self.flags.contains(CpuFlags::CARRY))
     }
 
     fn bit(&mut self, address_mode: &AddressingMode) {
@@ -538,47 +657,29 @@ fn asl(&mut self, address_mode: &AddressingMode) -> u8 {
         } else {
             self.flags.remove(CpuFlags::ZERO)
         }
-        if result & 0b0100_0000 == 0b0100_0000 {
-            self.flags.insert(CpuFlags::OVERFLOW)
-        }
-        if result & 0b1000_0000 == 0b1000_0000 {
-            self.flags.insert(CpuFlags::NEGATIVE)
-        }
+        // V and N are copied from the operand, not the result
+        self.flags.set(CpuFlags::OVERFLOW, param & 0b0100_0000 != 0);
+        self.flags.set(CpuFlags::NEGATIVE, param & 0b1000_0000 != 0);
     }
 
     fn bmi(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if self.flags.contains(CpuFlags::NEGATIVE) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
-        }
+        self.branch(self.flags.contains(CpuFlags::NEGATIVE))
     }
 
     fn bne(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if !self.flags.contains(CpuFlags::ZERO) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
-        }
+        self.branch(!self.flags.contains(CpuFlags::ZERO))
     }
 
     fn bpl(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if !self.flags.contains(CpuFlags::NEGATIVE) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
-        }
+        self.branch(!self.flags.contains(CpuFlags::NEGATIVE))
     }
 
     fn bvc(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if !self.flags.contains(CpuFlags::OVERFLOW) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
-        }
+        self.branch(!self.flags.contains(CpuFlags::OVERFLOW))
     }
 
     fn bvs(&mut self) {
-        let param = self.bus.mem_read(self.program_counter) as i8;
-        if self.flags.contains(CpuFlags::OVERFLOW) {
-            self.program_counter = self.program_counter.wrapping_add(1).wrapping_add(param as u16)
-        }
+        self.branch(self.flags.contains(CpuFlags::OVERFLOW))
     }
 
     fn clc(&mut self) {
@@ -771,11 +872,7 @@ fn asl(&mut self, address_mode: &AddressingMode) -> u8 {
             param << 1
         };
         self.bus.mem_write(self.calculate_address(address_mode), result);
-        if result >> 7 == 1 {
-            self.flags.insert(CpuFlags::NEGATIVE)
-        } else {
-            self.flags.remove(CpuFlags::NEGATIVE)
-        }
+        self.update_zero_and_negative_flags(result)
     }
 
     fn rol_accumulator(&mut self) {
@@ -810,11 +907,7 @@ fn asl(&mut self, address_mode: &AddressingMode) -> u8 {
             param >> 1 
         };
         self.bus.mem_write(self.calculate_address(address_mode), result);
-        if result >> 7 == 1 {
-            self.flags.insert(CpuFlags::NEGATIVE)
-        } else {
-            self.flags.remove(CpuFlags::NEGATIVE)
-        }
+        self.update_zero_and_negative_flags(result)
     }
     fn ror_accumulator(&mut self) {
         let param = self.register_a;
@@ -981,4 +1074,49 @@ mod test {
 
         assert_eq!(cpu.register_a, 0x65);
     }
+
+    #[test]
+    fn test_branch_and_page_crossing_cycles() {
+        let bus = Bus::new();
+        let mut cpu = Cpu::new(bus);
+        // $0600 BNE +0, $0602 BNE -128 (to $0584), $0584 LDX #$01, LDA $05FF,X, STA $05FF,X
+        cpu.load(&vec![0xd0, 0x00, 0xd0, 0x80]);
+        for (i, byte) in [0xa2, 0x01, 0xbd, 0xff, 0x05, 0x9d, 0xff, 0x05].iter().enumerate() {
+            cpu.bus.mem_write(0x584 + i as u16, *byte);
+        }
+        cpu.program_counter = 0x600;
+        let mut cycles = Vec::new();
+        for _ in 0..5 {
+            let before = cpu.bus.cycles();
+            cpu.step().unwrap();
+            cycles.push(cpu.bus.cycles() - before);
+        }
+        assert_eq!(cycles, [3, 4, 2, 5, 5]);
+        assert_eq!(cpu.program_counter, 0x58c);
+        assert_eq!(cpu.register_a, 0xd0);
+    }
+
+    #[test]
+    fn test_bit_copies_operand_bits() {
+        let bus = Bus::new();
+        let mut cpu = Cpu::new(bus);
+        cpu.bus.mem_write(0x10, 0xc0);
+        cpu.bus.mem_write(0x11, 0x01);
+        cpu.program_counter = 0x600;
+        cpu.load_and_run(&vec![0xa9, 0x01, 0x24, 0x10, 0x24, 0x11, 0x00]);
+
+        assert!(!cpu.flags.contains(CpuFlags::OVERFLOW));
+        assert!(!cpu.flags.contains(CpuFlags::NEGATIVE));
+        assert!(!cpu.flags.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_unknown_opcode_is_an_error() {
+        let bus = Bus::new();
+        let mut cpu = Cpu::new(bus);
+        cpu.load(&vec![0x02]);
+        cpu.program_counter = 0x600;
+        assert_eq!(cpu.step(), Err("Unknown opcode $02 at $0600".to_string()));
+        assert_eq!(cpu.program_counter, 0x600);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,207 @@
+use lazy_static::lazy_static;
+
+use crate::cartridge::{Cartridge, Mirroring, Region};
+
+lazy_static! {
+    /// The dumps listed in gamedb.txt, a small subset of the NES 2.0 database until the full list is generated
+    pub static ref DATABASE: GameDb = GameDb::parse(include_str!("gamedb.txt")).unwrap();
+}
+
+/// Known good cartridge configuration for one dump
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameInfo {
+    pub crc32: u32,
+    pub sha1: Option<String>,
+    pub title: String,
+    pub mapper: u16,
+    pub submapper: u8,
+    /// None when the mirroring is controlled by the mapper and the header value is kept
+    pub mirroring: Option<Mirroring>,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub region: Region,
+}
+
+pub struct GameDb {
+    games: Vec<GameInfo>,
+}
+
+impl GameDb {
+    /// Parses the line format documented in gamedb.txt
+    pub fn parse(text: &str) -> Result<GameDb, String> {
+        let mut games = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            games.push(parse_line(line).map_err(|e| format!("Game database line {}: {}", index + 1, e))?);
+        }
+        Ok(GameDb { games })
+    }
+
+    /// Looks a dump up by CRC32 of PRG+CHR, also checking the SHA-1 when the entry has one
+    pub fn find(&self, prg_rom: &[u8], chr_rom: &[u8]) -> Option<&GameInfo> {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(prg_rom);
+        hasher.update(chr_rom);
+        let crc32 = hasher.finalize();
+
+        let mut sha1 = None;
+        self.games.iter().find(|game| {
+            game.crc32 == crc32
+                && match &game.sha1 {
+                    Some(expected) => {
+                        let actual = sha1.get_or_insert_with(|| {
+                            let mut hasher = sha1_smol::Sha1::new();
+                            hasher.update(prg_rom);
+                            hasher.update(chr_rom);
+                            hasher.digest().to_string()
+                        });
+                        expected.eq_ignore_ascii_case(actual)
+                    }
+                    None => true,
+                }
+        })
+    }
+
+    /// Overrides the header values of a known game. Returns a message for the detected title and each
+    /// value that differed from the header.
+    pub fn apply(&self, cartridge: &mut Cartridge) -> Vec<String> {
+        let game = match self.find(&cartridge.prg_rom, &cartridge.chr_rom) {
+            Some(game) => game,
+            None => return vec![],
+        };
+        let mut messages = vec![format!("Game database: detected {}", game.title)];
+        let mut overridden = |field: &str, header: String, database: String| {
+            if header != database {
+                messages.push(format!("Game database: overriding {} {} from header with {}", field, header, database));
+            }
+        };
+
+        overridden("mapper", cartridge.mapper.to_string(), game.mapper.to_string());
+        overridden("submapper", cartridge.submapper.to_string(), game.submapper.to_string());
+        if let Some(mirroring) = game.mirroring {
+            overridden("mirroring", format!("{:?}", cartridge.mirroring), format!("{:?}", mirroring));
+            cartridge.mirroring = mirroring;
+        }
+        overridden("PRG RAM size", cartridge.prg_ram_size.to_string(), game.prg_ram_size.to_string());
+        overridden("PRG NVRAM size", cartridge.prg_nvram_size.to_string(), game.prg_nvram_size.to_string());
+        overridden("CHR RAM size", cartridge.chr_ram_size.to_string(), game.chr_ram_size.to_string());
+        overridden("region", format!("{:?}", cartridge.region), format!("{:?}", game.region));
+
+        cartridge.mapper = game.mapper;
+        cartridge.submapper = game.submapper;
+        cartridge.prg_ram_size = game.prg_ram_size;
+        cartridge.prg_nvram_size = game.prg_nvram_size;
+        cartridge.battery = game.prg_nvram_size > 0 || cartridge.chr_nvram_size > 0;
+        cartridge.chr_ram_size = game.chr_ram_size;
+        cartridge.region = game.region;
+        cartridge.title = Some(game.title.clone());
+        messages
+    }
+}
+
+fn parse_line(line: &str) -> Result<GameInfo, String> {
+    let mut rest = line;
+    let mut next = |name: &str| {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            return Err(format!("missing {}", name));
+        }
+        let (column, tail) = rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len()));
+        rest = tail;
+        Ok(column)
+    };
+    let number = |name: &str, value: &str| value.parse::<usize>().map_err(|_| format!("bad {} '{}'", name, value));
+
+    let crc32 = next("crc32")?;
+    let crc32 = u32::from_str_radix(crc32, 16).map_err(|_| format!("bad crc32 '{}'", crc32))?;
+    let sha1 = match next("sha1")? {
+        "-" => None,
+        sha1 if sha1.len() == 40 => Some(sha1.to_string()),
+        sha1 => return Err(format!("bad sha1 '{}'", sha1)),
+    };
+    let mapper = number("mapper", next("mapper")?)? as u16;
+    let submapper = number("submapper", next("submapper")?)? as u8;
+    let mirroring = match next("mirroring")? {
+        "H" => Some(Mirroring::Horizontal),
+        "V" => Some(Mirroring::Vertical),
+        "4" => Some(Mirroring::FourScreen),
+        "-" => None,
+        mirroring => return Err(format!("bad mirroring '{}'", mirroring)),
+    };
+    let prg_ram_size = number("prg_ram", next("prg_ram")?)?;
+    let prg_nvram_size = number("prg_nvram", next("prg_nvram")?)?;
+    let chr_ram_size = number("chr_ram", next("chr_ram")?)?;
+    let region = match next("region")? {
+        "N" => Region::Ntsc,
+        "P" => Region::Pal,
+        "D" => Region::Dendy,
+        region => return Err(format!("bad region '{}'", region)),
+    };
+    let title = rest.trim().to_string();
+    if title.is_empty() {
+        return Err("missing title".to_string());
+    }
+
+    Ok(GameInfo {
+        crc32,
+        sha1,
+        title,
+        mapper,
+        submapper,
+        mirroring,
+        prg_ram_size,
+        prg_nvram_size,
+        chr_ram_size,
+        region,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test::ines_image;
+
+    #[test]
+    fn test_embedded_database_parses() {
+        assert!(DATABASE.games.iter().all(|game| !game.title.is_empty()));
+        let game = DATABASE.games.iter().find(|game| game.crc32 == 0x3337EC46).unwrap();
+        assert_eq!(game.title, "Super Mario Bros. (World)");
+        assert_eq!((game.mapper, game.mirroring), (0, Some(Mirroring::Vertical)));
+    }
+
+    #[test]
+    fn test_overrides_header() {
+        let mut cartridge = Cartridge::from_ines(&ines_image(0, 2, 1, 0)).unwrap();
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&cartridge.prg_rom);
+        hasher.update(&cartridge.chr_rom);
+        let crc32 = hasher.finalize();
+
+        let db = GameDb::parse(&format!("# comment\n{:08x} - 4 1 - 0 1024 0 P Some Game (USA)\n", crc32)).unwrap();
+        let messages = db.apply(&mut cartridge);
+        assert_eq!(cartridge.title.as_deref(), Some("Some Game (USA)"));
+        assert_eq!(cartridge.mapper, 4);
+        assert_eq!(cartridge.submapper, 1);
+        assert_eq!(cartridge.mirroring, Mirroring::Horizontal);
+        assert_eq!(cartridge.prg_nvram_size, 1024);
+        assert!(cartridge.battery);
+        assert_eq!(cartridge.region, Region::Pal);
+        assert!(messages.iter().any(|m| m.contains("overriding mapper 0 from header with 4")));
+        assert!(!messages.iter().any(|m| m.contains("mirroring")));
+    }
+
+    #[test]
+    fn test_sha1_must_match() {
+        let mut cartridge = Cartridge::from_ines(&ines_image(0, 1, 1, 0)).unwrap();
+        let crc32 = crc32fast::hash(&[cartridge.prg_rom.clone(), cartridge.chr_rom.clone()].concat());
+        let line = format!("{:08X} {} 1 0 V 8192 0 0 N Other Game", crc32, "0".repeat(40));
+        let db = GameDb::parse(&line).unwrap();
+        assert!(db.apply(&mut cartridge).is_empty());
+        assert_eq!(cartridge.mapper, 0);
+        assert!(GameDb::parse("1234 - 1 0 X 0 0 0 N Bad").err().unwrap().contains("line 1: bad mirroring"));
+    }
+}
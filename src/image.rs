@@ -0,0 +1,77 @@
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// Saves packed RGB24 pixels as a PNG file
+pub fn write_png(path: &Path, width: usize, height: usize, rgb: &[u8]) -> Result<(), String> {
+    let error = |e: &dyn std::fmt::Display| format!("Can't write {}: {}", path.display(), e);
+    let file = File::create(path).map_err(|e| error(&e))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|e| error(&e))?;
+    writer.write_image_data(rgb).map_err(|e| error(&e))
+}
+
+/// Loads a PNG file as packed RGB24 pixels: (width, height, pixels). Transparency is dropped.
+pub fn read_png(path: &Path) -> Result<(usize, usize, Vec<u8>), String> {
+    let error = |e: &dyn std::fmt::Display| format!("Can't read {}: {}", path.display(), e);
+    let file = File::open(path).map_err(|e| error(&e))?;
+    let mut decoder = png::Decoder::new(BufReader::new(file));
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|e| error(&e))?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(|e| error(&e))?;
+    pixels.truncate(info.buffer_size());
+    let rgb = match info.color_type {
+        png::ColorType::Rgb => pixels,
+        png::ColorType::Rgba => pixels.chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|p| [*p, *p, *p]).collect(),
+        png::ColorType::GrayscaleAlpha => pixels.chunks_exact(2).flat_map(|p| [p[0], p[0], p[0]]).collect(),
+        png::ColorType::Indexed => return Err(error(&"unexpanded palette image")),
+    };
+    Ok((info.width as usize, info.height as usize, rgb))
+}
+
+/// Compares two RGB24 images of the same size. Returns how many pixels differ and a picture of the
+/// differences: matching pixels dimmed to grey, the others in red.
+pub fn diff(expected: &[u8], actual: &[u8]) -> (usize, Vec<u8>) {
+    let mut differences = 0;
+    let picture = expected
+        .chunks_exact(3)
+        .zip(actual.chunks_exact(3))
+        .flat_map(|(expected, actual)| {
+            if expected == actual {
+                let grey = ((expected[0] as u16 + expected[1] as u16 + expected[2] as u16) / 12) as u8;
+                [grey, grey, grey]
+            } else {
+                differences += 1;
+                [0xFF, 0x00, 0x00]
+            }
+        })
+        .collect();
+    (differences, picture)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_png_round_trip_and_diff() {
+        let path = std::env::temp_dir().join(format!("rust-nes-image-{}.png", std::process::id()));
+        let rgb: Vec<u8> = (0..4 * 2 * 3).map(|i| i as u8 * 10).collect();
+        write_png(&path, 4, 2, &rgb).unwrap();
+        let (width, height, read) = read_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((width, height), (4, 2));
+        assert_eq!(read, rgb);
+
+        let mut changed = rgb.clone();
+        changed[3] ^= 1;
+        let (differences, picture) = diff(&rgb, &changed);
+        assert_eq!(differences, 1);
+        assert_eq!(&picture[3..6], &[0xFF, 0, 0]);
+        assert_eq!(&picture[0..3], &[2, 2, 2]);
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::Path;
+
+use crate::joypad::JoypadButton;
+
+/// Controller input for automated runs. Each entry gives the buttons held from a frame on:
+///
+/// ```text
+/// # frame  controller 1  [controller 2]
+/// 30       start
+/// 32       -
+/// 120      right,a       b
+/// ```
+///
+/// Entries are separated by new lines or `;`, `-` means no button, frames count from 0.
+pub struct InputScript {
+    entries: Vec<(u64, [JoypadButton; 2])>,
+}
+
+impl InputScript {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut entries: Vec<(u64, [JoypadButton; 2])> = Vec::new();
+        let lines = text
+            .split(['\n', ';'])
+            .map(|line| line.split('#').next().unwrap().trim());
+        for (number, line) in lines.enumerate().filter(|(_, line)| !line.is_empty()) {
+            let error = |message: String| format!("Input script entry {}: {}", number + 1, message);
+            let mut columns = line.split_whitespace();
+            let frame = columns.next().unwrap();
+            let frame = frame
+                .parse::<u64>()
+                .map_err(|_| error(format!("bad frame '{}'", frame)))?;
+            if entries.last().is_some_and(|(last, _)| *last >= frame) {
+                return Err(error("frames must increase".to_string()));
+            }
+            let mut buttons = [JoypadButton::empty(); 2];
+            for port in buttons.iter_mut() {
+                for name in columns.next().unwrap_or("-").split(',').filter(|name| *name != "-") {
+                    *port |= JoypadButton::from_name(&name.to_lowercase())
+                        .ok_or_else(|| error(format!("unknown button '{}'", name)))?;
+                }
+            }
+            if columns.next().is_some() {
+                return Err(error("too many columns".to_string()));
+            }
+            entries.push((frame, buttons));
+        }
+        Ok(InputScript { entries })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path.display(), e))?;
+        InputScript::parse(&text)
+    }
+
+    /// Buttons held on both controllers during `frame`
+    pub fn buttons(&self, frame: u64) -> [JoypadButton; 2] {
+        self.entries
+            .iter()
+            .take_while(|(start, _)| *start <= frame)
+            .last()
+            .map_or([JoypadButton::empty(); 2], |(_, buttons)| *buttons)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_hold() {
+        let script = InputScript::parse("# title screen\n30 start\n32 -; 120 Right,a b").unwrap();
+        assert_eq!(script.buttons(0), [JoypadButton::empty(); 2]);
+        assert_eq!(script.buttons(31), [JoypadButton::START, JoypadButton::empty()]);
+        assert_eq!(script.buttons(32), [JoypadButton::empty(); 2]);
+        assert_eq!(
+            script.buttons(500),
+            [JoypadButton::RIGHT | JoypadButton::A, JoypadButton::B]
+        );
+
+        assert!(InputScript::parse("10 jump")
+            .err()
+            .unwrap()
+            .contains("entry 1: unknown button 'jump'"));
+        assert!(InputScript::parse("10 a; 5 b")
+            .err()
+            .unwrap()
+            .contains("frames must increase"));
+    }
+}
@@ -0,0 +1,118 @@
+use bitflags::bitflags;
+
+bitflags! {
+    /// # Standard controller https://www.nesdev.org/wiki/Standard_controller
+    ///
+    /// Buttons in the order they are shifted out of $4016/$4017.
+    ///
+    pub struct JoypadButton: u8 {
+        const A      = 0b00000001;
+        const B      = 0b00000010;
+        const SELECT = 0b00000100;
+        const START  = 0b00001000;
+        const UP     = 0b00010000;
+        const DOWN   = 0b00100000;
+        const LEFT   = 0b01000000;
+        const RIGHT  = 0b10000000;
+    }
+}
+
+impl JoypadButton {
+    /// Button from its lower case name ("a", "start", "left", ...)
+    pub fn from_name(name: &str) -> Option<JoypadButton> {
+        match name {
+            "a" => Some(JoypadButton::A),
+            "b" => Some(JoypadButton::B),
+            "select" => Some(JoypadButton::SELECT),
+            "start" => Some(JoypadButton::START),
+            "up" => Some(JoypadButton::UP),
+            "down" => Some(JoypadButton::DOWN),
+            "left" => Some(JoypadButton::LEFT),
+            "right" => Some(JoypadButton::RIGHT),
+            _ => None,
+        }
+    }
+}
+
+/// Controller port: a parallel-in shift register latched while the strobe bit is high
+pub struct Joypad {
+    strobe: bool,
+    index: u8,
+    buttons: JoypadButton,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            strobe: false,
+            index: 0,
+            buttons: JoypadButton::empty(),
+        }
+    }
+
+    pub fn set_buttons(&mut self, buttons: JoypadButton) {
+        self.buttons = buttons;
+    }
+
+    /// Presses or releases `button`, leaving the others as they are
+    pub fn set_button(&mut self, button: JoypadButton, pressed: bool) {
+        self.buttons.set(button, pressed);
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.strobe = value & 1 != 0;
+        if self.strobe {
+            self.index = 0;
+        }
+    }
+
+    /// Next button state in bit 0; official controllers return 1 once all eight were read
+    pub fn read(&mut self) -> u8 {
+        if self.index > 7 {
+            return 1;
+        }
+        let value = (self.buttons.bits() >> self.index) & 1;
+        if !self.strobe {
+            self.index += 1;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_strobe_and_shift() {
+        let mut joypad = Joypad::new();
+        joypad.set_buttons(JoypadButton::A | JoypadButton::START | JoypadButton::RIGHT);
+        joypad.write(1);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1);
+        joypad.write(0);
+        let bits: Vec<u8> = (0..10).map(|_| joypad.read()).collect();
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+        joypad.write(1);
+        joypad.write(0);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 0);
+    }
+
+    #[test]
+    fn test_set_button() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(JoypadButton::B, true);
+        joypad.set_button(JoypadButton::UP, true);
+        joypad.set_button(JoypadButton::B, false);
+        joypad.write(0);
+        let bits: Vec<u8> = (0..8).map(|_| joypad.read()).collect();
+        assert_eq!(bits, [0, 0, 0, 0, 1, 0, 0, 0]);
+    }
+}
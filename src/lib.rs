@@ -0,0 +1,19 @@
+//! NES emulator core: everything but the SDL frontend, so test harnesses and tools can drive it headless.
+
+pub mod cpu;
+pub mod opscode;
+pub mod bus;
+pub mod apu;
+pub mod archive;
+pub mod cartridge;
+pub mod gamedb;
+pub mod image;
+pub mod input_script;
+pub mod joypad;
+pub mod mapper;
+pub mod nes;
+pub mod palette;
+pub mod patch;
+pub mod ppu;
+pub mod save;
+pub mod trace;
@@ -1,147 +1,234 @@
-pub mod cpu;
-pub mod opscode;
-pub mod bus;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
 
-use bus::Memory;
-use cpu::Cpu;
-use rand::Rng;
-use bus::Bus;
+use rust_nes::cartridge::{Cartridge, Region, FDS_MAPPER};
+use rust_nes::image;
+use rust_nes::input_script::InputScript;
+use rust_nes::joypad::JoypadButton;
+use rust_nes::nes::Nes;
+use rust_nes::palette::Palette;
+use rust_nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
 
+use sdl2::audio::AudioSpecDesired;
 use sdl2::event::Event;
-use sdl2::EventPump;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
 use sdl2::pixels::PixelFormatEnum;
-use std::time::Duration;
 
-#[macro_use]
-extern crate lazy_static;
-
-#[macro_use]
-extern crate bitflags;
-
-fn color(byte: u8) -> Color {
-    match byte {
-        0 => sdl2::pixels::Color::BLACK,
-        1 => sdl2::pixels::Color::WHITE,
-        2 | 9 => sdl2::pixels::Color::GREY,
-        3 | 10 => sdl2::pixels::Color::RED,
-        4 | 11 => sdl2::pixels::Color::GREEN,
-        5 | 12 => sdl2::pixels::Color::BLUE,
-        6 | 13 => sdl2::pixels::Color::MAGENTA,
-        7 | 14 => sdl2::pixels::Color::YELLOW,
-        _ => sdl2::pixels::Color::CYAN,
-    }
+const USAGE: &str = "Usage: rust-nes <rom> [options]
+
+Options:
+  --patch <file>      apply an IPS, BPS or UPS patch before loading (can be repeated)
+  --entry <n>         ROM to pick from an archive holding several, counting from 0
+  --bios <file>       Famicom Disk System BIOS, needed for .fds images
+  --palette <file>    .pal palette file (192 or 1536 bytes)
+  --region <region>   ntsc, pal or dendy instead of the region from the header
+  --frames <n>        run headless for n frames, without opening a window
+  --input <file>      controller input script for headless runs (see input_script.rs)
+  --dump <n,n,...>    frames to save as PNG, implies headless (frames count from 1)
+  --output <dir>      directory for the PNG files, the current one by default
+
+Keys (first controller):
+  arrows or W/A/S/D   D-pad
+  X or K              A
+  Z or J              B
+  Enter               Start
+  Space or RShift     Select";
+
+const WINDOW_SCALE: u32 = 3;
+const SAMPLE_RATE: i32 = 48_000;
+/// Frames of sound kept queued: the emulation waits on the audio device rather than on the display, so the
+/// speed follows the sound card and the queue neither runs dry nor grows
+const AUDIO_LATENCY_FRAMES: f64 = 3.0;
+
+#[derive(Default)]
+struct Options {
+    rom: PathBuf,
+    patches: Vec<PathBuf>,
+    entry: Option<usize>,
+    bios: Option<PathBuf>,
+    palette: Option<PathBuf>,
+    region: Option<Region>,
+    frames: Option<u64>,
+    input: Option<PathBuf>,
+    dump: Vec<u64>,
+    output: PathBuf,
 }
 
-fn read_screen_state(cpu: &Cpu, frame: &mut [u8; 32 * 3 * 32]) -> bool {
-    let mut frame_idx = 0;
-    let mut update = false;
-    for i in 0x0200..0x600 {
-        let color_idx = cpu.bus.mem_read(i as u16);
-        let (b1, b2, b3) = color(color_idx).rgb();
-        if frame[frame_idx] != b1 || frame[frame_idx + 1] != b2 || frame[frame_idx + 2] != b3 {
-            frame[frame_idx] = b1;
-            frame[frame_idx + 1] = b2;
-            frame[frame_idx + 2] = b3;
-            update = true;
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options { output: PathBuf::from("."), ..Options::default() };
+        let mut rom = None;
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            let number = |value: String| value.parse::<u64>().map_err(|_| format!("Bad number '{}'", value));
+            match arg.as_str() {
+                "--patch" => options.patches.push(PathBuf::from(value()?)),
+                "--entry" => options.entry = Some(number(value()?)? as usize),
+                "--bios" => options.bios = Some(PathBuf::from(value()?)),
+                "--palette" => options.palette = Some(PathBuf::from(value()?)),
+                "--region" => {
+                    options.region = Some(match value()?.to_lowercase().as_str() {
+                        "ntsc" => Region::Ntsc,
+                        "pal" => Region::Pal,
+                        "dendy" => Region::Dendy,
+                        region => return Err(format!("Unknown region '{}'", region)),
+                    })
+                }
+                "--frames" => options.frames = Some(number(value()?)?),
+                "--input" => options.input = Some(PathBuf::from(value()?)),
+                "--dump" => {
+                    for frame in value()?.split(',') {
+                        options.dump.push(number(frame.trim().to_string())?);
+                    }
+                }
+                "--output" => options.output = PathBuf::from(value()?),
+                _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+                _ if rom.is_none() => rom = Some(PathBuf::from(arg)),
+                _ => return Err(format!("Unexpected argument {}", arg)),
+            }
         }
-        frame_idx += 3;
+        options.rom = rom.ok_or("No ROM given")?;
+        Ok(options)
+    }
+
+    fn headless(&self) -> bool {
+        self.frames.is_some() || self.input.is_some() || !self.dump.is_empty()
     }
-    update
 }
 
-fn handle_user_input(cpu: &mut Cpu, event_pump: &mut EventPump) {
-    for event in event_pump.poll_iter() {
-        match event {
-            Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
-                std::process::exit(0)
-            },
-            Event::KeyDown { keycode: Some(Keycode::W), .. } => {
-                cpu.bus.mem_write(0xff, 0x77);
-            },
-            Event::KeyDown { keycode: Some(Keycode::S), .. } => {
-                cpu.bus.mem_write(0xff, 0x73);
-            },
-            Event::KeyDown { keycode: Some(Keycode::A), .. } => {
-                cpu.bus.mem_write(0xff, 0x61);
-            },
-            Event::KeyDown { keycode: Some(Keycode::D), .. } => {
-                cpu.bus.mem_write(0xff, 0x64);
-            }
-            _ => {/* do nothing */}
+fn load(options: &Options) -> Result<Nes, String> {
+    let choose_entry = |names: &[String]| options.entry.filter(|entry| *entry < names.len());
+    let (cartridge, messages) = Cartridge::load(&options.rom, &options.patches, &choose_entry)?;
+    for message in messages {
+        println!("{}", message);
+    }
+    let mut nes = if cartridge.mapper == FDS_MAPPER {
+        let bios_path = options.bios.as_ref().ok_or("FDS images need the disk system BIOS, pass it with --bios")?;
+        let bios = fs::read(bios_path).map_err(|e| format!("Can not read BIOS {}: {}", bios_path.display(), e))?;
+        Nes::with_fds(&cartridge, &bios)?
+    } else {
+        Nes::new(&cartridge)?
+    };
+    if let Some(region) = options.region {
+        nes.bus_mut().set_region(region);
+    }
+    if let Some(path) = &options.palette {
+        nes.set_palette(Palette::load(path)?);
+    }
+    Ok(nes)
+}
+
+/// Runs without SDL, saving the frames asked for with --dump
+fn run_headless(nes: &mut Nes, options: &Options) -> Result<(), String> {
+    let last_dump = options.dump.iter().copied().max().unwrap_or(0);
+    let frames = options.frames.unwrap_or(0).max(last_dump);
+    let name = options.rom.file_stem().and_then(|name| name.to_str()).unwrap_or("frame");
+    let script = match &options.input {
+        Some(path) => InputScript::load(path)?,
+        None => InputScript::parse("")?,
+    };
+    for frame in 1..=frames {
+        nes.apply_input(&script);
+        nes.run_frame()?;
+        if options.dump.contains(&frame) {
+            let path = options.output.join(format!("{}-{:05}.png", name, frame));
+            image::write_png(&path, SCREEN_WIDTH, SCREEN_HEIGHT, &nes.rgb_frame())?;
+            println!("Saved {}", path.display());
         }
     }
+    Ok(())
 }
 
-fn main() {
-    // init sdl2
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+/// Controller button played by a key
+fn key_button(keycode: Keycode) -> Option<JoypadButton> {
+    match keycode {
+        Keycode::Up | Keycode::W => Some(JoypadButton::UP),
+        Keycode::Down | Keycode::S => Some(JoypadButton::DOWN),
+        Keycode::Left | Keycode::A => Some(JoypadButton::LEFT),
+        Keycode::Right | Keycode::D => Some(JoypadButton::RIGHT),
+        Keycode::X | Keycode::K => Some(JoypadButton::A),
+        Keycode::Z | Keycode::J => Some(JoypadButton::B),
+        Keycode::Return => Some(JoypadButton::START),
+        Keycode::Space | Keycode::RShift => Some(JoypadButton::SELECT),
+        _ => None,
+    }
+}
+
+fn run_window(nes: &mut Nes, rom: &Path) -> Result<(), String> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
+    let title = rom.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
     let window = video_subsystem
-        .window("Snake game", (32.0 * 10.0) as u32, (32.0 * 10.0) as u32)
+        .window(&title, SCREEN_WIDTH as u32 * WINDOW_SCALE, SCREEN_HEIGHT as u32 * WINDOW_SCALE)
         .position_centered()
-        .build().unwrap();
-
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    canvas.set_scale(10.0, 10.0).unwrap();
+        .build()
+        .map_err(|e| e.to_string())?;
 
+    let mut canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
     let creator = canvas.texture_creator();
     let mut texture = creator
-        .create_texture_target(PixelFormatEnum::RGB24, 32, 32).unwrap();
-    
-
-    let game_code: Vec<u8> = vec![
-        0x20, 0x06, 0x06, 0x20, 0x38, 0x06, 0x20, 0x0d, 0x06, 0x20, 0x2a, 0x06, 0x60, 0xa9, 0x02,
-        0x85, 0x02, 0xa9, 0x04, 0x85, 0x03, 0xa9, 0x11, 0x85, 0x10, 0xa9, 0x10, 0x85, 0x12, 0xa9,
-        0x0f, 0x85, 0x14, 0xa9, 0x04, 0x85, 0x11, 0x85, 0x13, 0x85, 0x15, 0x60, 0xa5, 0xfe, 0x85,
-        0x00, 0xa5, 0xfe, 0x29, 0x03, 0x18, 0x69, 0x02, 0x85, 0x01, 0x60, 0x20, 0x4d, 0x06, 0x20,
-        0x8d, 0x06, 0x20, 0xc3, 0x06, 0x20, 0x19, 0x07, 0x20, 0x20, 0x07, 0x20, 0x2d, 0x07, 0x4c,
-        0x38, 0x06, 0xa5, 0xff, 0xc9, 0x77, 0xf0, 0x0d, 0xc9, 0x64, 0xf0, 0x14, 0xc9, 0x73, 0xf0,
-        0x1b, 0xc9, 0x61, 0xf0, 0x22, 0x60, 0xa9, 0x04, 0x24, 0x02, 0xd0, 0x26, 0xa9, 0x01, 0x85,
-        0x02, 0x60, 0xa9, 0x08, 0x24, 0x02, 0xd0, 0x1b, 0xa9, 0x02, 0x85, 0x02, 0x60, 0xa9, 0x01,
-        0x24, 0x02, 0xd0, 0x10, 0xa9, 0x04, 0x85, 0x02, 0x60, 0xa9, 0x02, 0x24, 0x02, 0xd0, 0x05,
-        0xa9, 0x08, 0x85, 0x02, 0x60, 0x60, 0x20, 0x94, 0x06, 0x20, 0xa8, 0x06, 0x60, 0xa5, 0x00,
-        0xc5, 0x10, 0xd0, 0x0d, 0xa5, 0x01, 0xc5, 0x11, 0xd0, 0x07, 0xe6, 0x03, 0xe6, 0x03, 0x20,
-        0x2a, 0x06, 0x60, 0xa2, 0x02, 0xb5, 0x10, 0xc5, 0x10, 0xd0, 0x06, 0xb5, 0x11, 0xc5, 0x11,
-        0xf0, 0x09, 0xe8, 0xe8, 0xe4, 0x03, 0xf0, 0x06, 0x4c, 0xaa, 0x06, 0x4c, 0x35, 0x07, 0x60,
-        0xa6, 0x03, 0xca, 0x8a, 0xb5, 0x10, 0x95, 0x12, 0xca, 0x10, 0xf9, 0xa5, 0x02, 0x4a, 0xb0,
-        0x09, 0x4a, 0xb0, 0x19, 0x4a, 0xb0, 0x1f, 0x4a, 0xb0, 0x2f, 0xa5, 0x10, 0x38, 0xe9, 0x20,
-        0x85, 0x10, 0x90, 0x01, 0x60, 0xc6, 0x11, 0xa9, 0x01, 0xc5, 0x11, 0xf0, 0x28, 0x60, 0xe6,
-        0x10, 0xa9, 0x1f, 0x24, 0x10, 0xf0, 0x1f, 0x60, 0xa5, 0x10, 0x18, 0x69, 0x20, 0x85, 0x10,
-        0xb0, 0x01, 0x60, 0xe6, 0x11, 0xa9, 0x06, 0xc5, 0x11, 0xf0, 0x0c, 0x60, 0xc6, 0x10, 0xa5,
-        0x10, 0x29, 0x1f, 0xc9, 0x1f, 0xf0, 0x01, 0x60, 0x4c, 0x35, 0x07, 0xa0, 0x00, 0xa5, 0xfe,
-        0x91, 0x00, 0x60, 0xa6, 0x03, 0xa9, 0x00, 0x81, 0x10, 0xa2, 0x00, 0xa9, 0x01, 0x81, 0x10,
-        0x60, 0xa6, 0xff, 0xea, 0xea, 0xca, 0xd0, 0xfb, 0x60,
-    ];
-
-
-    //load the game
-    let bus = Bus::new();
-    let mut cpu = Cpu::new(bus);
-    cpu.load(&game_code);
-    cpu.reset();
-    cpu.program_counter = 0x0600;
-
-    let mut screen_state = [0 as u8; 32 * 3 * 32];
-    let mut rng = rand::thread_rng();
-
-    // run the game cycle
-    cpu.run_with_callback(move |cpu| {
-        handle_user_input(cpu, &mut event_pump);
-
-        cpu.bus.mem_write(0xfe, rng.gen_range(1, 16));
-
-        if read_screen_state(cpu, &mut screen_state) {
-            texture.update(None, &screen_state, 32 * 3).unwrap();
-
-            canvas.copy(&texture, None, None).unwrap();
-
-            canvas.present();
+        .create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)
+        .map_err(|e| e.to_string())?;
+
+    let audio_subsystem = sdl_context.audio()?;
+    let spec = AudioSpecDesired { freq: Some(SAMPLE_RATE), channels: Some(1), samples: Some(1024) };
+    let audio = audio_subsystem.open_queue::<i16, _>(None, &spec)?;
+    let sample_rate = audio.spec().freq as u32;
+    nes.set_sample_rate(sample_rate);
+    let queue_limit = sample_rate as f64 / nes.bus().region().frame_rate() * AUDIO_LATENCY_FRAMES;
+    let mut samples = Vec::new();
+    audio.resume();
+
+    nes.bus_mut().attach_save_file(rom)?;
+    loop {
+        for event in event_pump.poll_iter() {
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
+                    return nes.bus_mut().flush_save_file();
+                }
+                Event::KeyDown { keycode: Some(keycode), .. } | Event::KeyUp { keycode: Some(keycode), .. } => {
+                    if let Some(button) = key_button(keycode) {
+                        let pressed = matches!(event, Event::KeyDown { .. });
+                        nes.bus_mut().joypad_mut(0).set_button(button, pressed);
+                    }
+                }
+                _ => {}
+            }
         }
+        nes.run_frame()?;
+        samples.clear();
+        nes.take_samples(&mut samples);
+        audio.queue(&samples);
+        texture.update(None, &nes.rgb_frame(), SCREEN_WIDTH * 3).map_err(|e| e.to_string())?;
+        canvas.copy(&texture, None, None)?;
+        canvas.present();
+        // the queue size is in bytes
+        while audio.size() as f64 / 2.0 > queue_limit {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
 
-        ::std::thread::sleep(std::time::Duration::new(0, 70_000));
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            std::process::exit(2);
+        }
+    };
+    let result = load(&options).and_then(|mut nes| {
+        if options.headless() {
+            run_headless(&mut nes, &options)
+        } else {
+            run_window(&mut nes, &options.rom)
+        }
     });
-
-}
\ No newline at end of file
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
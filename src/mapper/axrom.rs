@@ -0,0 +1,98 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+/// Mapper 7 (AxROM) https://www.nesdev.org/wiki/AxROM
+///
+/// One 32KB PRG bank register that also picks which CIRAM page is shown on all four nametables.
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_bank: u8,
+    mirroring: Mirroring,
+}
+
+impl Axrom {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let (chr, chr_is_ram) = super::chr_memory(cartridge);
+        Axrom {
+            prg_rom: cartridge.prg_rom.clone(),
+            chr,
+            chr_is_ram,
+            prg_bank: 0,
+            mirroring: Mirroring::SingleScreenLower,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x8000..=0xFFFF => {
+                let offset = self.prg_bank as usize * PRG_BANK_SIZE + (address as usize - 0x8000);
+                self.prg_rom[offset % self.prg_rom.len()]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x8000..=0xFFFF = address {
+            self.prg_bank = value & 0b111;
+            self.mirroring = if value & 0b1_0000 == 0 {
+                Mirroring::SingleScreenLower
+            } else {
+                Mirroring::SingleScreenUpper
+            };
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr[address as usize % self.chr.len()]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let len = self.chr.len();
+            self.chr[address as usize % len] = value;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    #[test]
+    fn test_prg_bank_and_single_screen() {
+        let mut mapper = Axrom::new(&Cartridge {
+            prg_rom: (0..8).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: Vec::new(),
+            mapper: 7,
+            submapper: 0,
+            mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        });
+        assert_eq!(mapper.cpu_read(0xFFFF), 0);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+        mapper.cpu_write(0x8000, 0b1_0101);
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        mapper.chr_write(0x1234, 0x42);
+        assert_eq!(mapper.chr_read(0x1234), 0x42);
+    }
+}
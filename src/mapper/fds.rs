@@ -0,0 +1,455 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::Mapper;
+
+pub const BIOS_SIZE: usize = 0x2000;
+/// Size of one disk side in a `.fds` image, which leaves out the gaps and CRCs
+pub const SIDE_SIZE: usize = 65500;
+/// A side with gaps and CRCs added, padded so games can append files
+const RAW_SIDE_SIZE: usize = 68000;
+/// 28300 bits of gap before the first block, 976 bits between blocks
+const LEAD_IN_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+const BLOCK_START_MARK: u8 = 0x80;
+/// CRCs are never checked ($4030 always reports a good CRC), any value will do
+const FAKE_CRC: [u8; 2] = [0x4D, 0x62];
+/// CPU cycles for the head to get back to the start of the disk, and per byte (~96.4 kbit/s)
+const HEAD_RETURN_CYCLES: u32 = 50000;
+const BYTE_CYCLES: u32 = 150;
+
+/// Famicom Disk System RAM adapter https://www.nesdev.org/wiki/Family_Computer_Disk_System
+///
+/// 32KB PRG RAM at $6000-$DFFF, the BIOS at $E000-$FFFF, 8KB CHR RAM, a timer IRQ and the disk drive.
+/// The drive moves one byte every `BYTE_CYCLES` while the motor runs, the way the BIOS expects. The
+/// expansion audio is not emulated.
+///
+/// Disks are kept in memory with their gaps and CRCs, all sides back to back. That image is what gets
+/// written to the save file so the `.fds` file itself is never modified.
+pub struct Fds {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    disk: Vec<u8>,
+    side_count: usize,
+    side: Option<usize>,
+
+    disk_registers_enabled: bool,
+    mirroring: Mirroring,
+
+    irq_reload: u16,
+    irq_counter: u16,
+    irq_enabled: bool,
+    irq_repeat: bool,
+    timer_irq: bool,
+
+    motor_on: bool,
+    reset_transfer: bool,
+    read_mode: bool,
+    crc_control: bool,
+    disk_ready: bool,
+    disk_irq_enabled: bool,
+    disk_irq: bool,
+
+    position: usize,
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    transfer_complete: bool,
+    read_data: u8,
+    write_data: u8,
+    crc_byte: usize,
+}
+
+impl Fds {
+    /// `cartridge.prg_rom` holds the disk sides as found in the `.fds` file, without the fwNES header
+    pub fn new(cartridge: &Cartridge, bios: &[u8]) -> Result<Self, String> {
+        if bios.len() != BIOS_SIZE {
+            return Err(format!("FDS BIOS must be {} bytes, got {}", BIOS_SIZE, bios.len()));
+        }
+        let side_count = cartridge.prg_rom.len() / SIDE_SIZE;
+        if side_count == 0 {
+            return Err("FDS image has no disk side".to_string());
+        }
+        Ok(Fds {
+            bios: bios.to_vec(),
+            prg_ram: vec![0; 0x8000],
+            chr_ram: vec![0; 0x2000],
+            disk: cartridge.prg_rom.chunks_exact(SIDE_SIZE).flat_map(add_gaps).collect(),
+            side_count,
+            side: Some(0),
+            disk_registers_enabled: false,
+            mirroring: Mirroring::Horizontal,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_enabled: false,
+            irq_repeat: false,
+            timer_irq: false,
+            motor_on: false,
+            reset_transfer: false,
+            read_mode: false,
+            crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            transfer_complete: false,
+            read_data: 0,
+            write_data: 0,
+            crc_byte: 0,
+        })
+    }
+
+    pub fn side_count(&self) -> usize {
+        self.side_count
+    }
+
+    /// The side in the drive (0 - disk 1 side A, 1 - disk 1 side B, ...), None when ejected
+    pub fn side(&self) -> Option<usize> {
+        self.side
+    }
+
+    /// Puts a side in the drive, None ejects the disk. Games only notice a swap when the drive stays
+    /// empty for a moment (about a second), so eject and insert from separate frames.
+    pub fn insert_side(&mut self, side: Option<usize>) -> Result<(), String> {
+        if let Some(side) = side {
+            if side >= self.side_count {
+                return Err(format!("Disk side {} does not exist, the image has {} sides", side, self.side_count));
+            }
+        }
+        self.side = side;
+        self.end_of_head = true;
+        self.scanning = false;
+        Ok(())
+    }
+
+    fn clock_drive(&mut self) {
+        let side = match (self.side, self.motor_on) {
+            (Some(side), true) => side,
+            _ => {
+                self.end_of_head = true;
+                self.scanning = false;
+                return;
+            }
+        };
+        if self.reset_transfer && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.delay = HEAD_RETURN_CYCLES;
+            self.end_of_head = false;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let offset = side * RAW_SIDE_SIZE + self.position;
+        if self.read_mode {
+            let data = self.disk[offset];
+            let mut raise_irq = self.disk_irq_enabled;
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // the block start mark is handed over without an IRQ
+                self.gap_ended = true;
+                raise_irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= raise_irq;
+            }
+        } else {
+            let mut data = 0;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+                self.crc_byte = 0;
+                if self.disk_ready {
+                    data = self.write_data;
+                }
+            } else {
+                data = FAKE_CRC[self.crc_byte % 2];
+                self.crc_byte += 1;
+            }
+            self.disk[offset] = data;
+            self.gap_ended = false;
+        }
+
+        self.position += 1;
+        if self.position >= RAW_SIDE_SIZE {
+            self.motor_on = false;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            if !self.irq_repeat {
+                self.irq_enabled = false;
+            }
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+}
+
+/// Turns a `.fds` side into what the drive head sees: a lead-in gap, then each block behind a start mark
+/// and followed by its CRC and a gap. Parsing stops at the first unknown block type.
+fn add_gaps(side: &[u8]) -> Vec<u8> {
+    let mut raw = vec![0; LEAD_IN_GAP];
+    let mut position = 0;
+    let mut file_size = 0;
+    while position < side.len() {
+        let len = match side[position] {
+            // disk info
+            1 => 56,
+            // file amount
+            2 => 2,
+            // file header, the file size sits at bytes 13-14
+            3 => {
+                if let Some(size) = side.get(position + 13..position + 15) {
+                    file_size = u16::from_le_bytes([size[0], size[1]]) as usize;
+                }
+                16
+            }
+            // file data
+            4 => 1 + file_size,
+            _ => break,
+        };
+        let end = std::cmp::min(position + len, side.len());
+        raw.push(BLOCK_START_MARK);
+        raw.extend(&side[position..end]);
+        raw.extend(FAKE_CRC);
+        raw.extend([0; BLOCK_GAP]);
+        position = end;
+    }
+    raw.resize(std::cmp::max(raw.len(), RAW_SIDE_SIZE), 0);
+    raw.truncate(RAW_SIDE_SIZE);
+    raw
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x4030 if self.disk_registers_enabled => {
+                let mut status = 0;
+                if self.timer_irq {
+                    status |= 0b0000_0001;
+                }
+                if self.transfer_complete {
+                    status |= 0b0000_0010;
+                }
+                if self.end_of_head {
+                    status |= 0b0100_0000;
+                }
+                self.transfer_complete = false;
+                self.timer_irq = false;
+                self.disk_irq = false;
+                status
+            }
+            0x4031 if self.disk_registers_enabled => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+                self.read_data
+            }
+            0x4032 if self.disk_registers_enabled => {
+                let mut status = 0x40;
+                if self.side.is_none() {
+                    // no disk, which also reads as write protected
+                    status |= 0b0000_0101;
+                }
+                if self.side.is_none() || !self.scanning {
+                    status |= 0b0000_0010;
+                }
+                status
+            }
+            // battery good
+            0x4033 if self.disk_registers_enabled => 0x80,
+            0x6000..=0xDFFF => self.prg_ram[address as usize - 0x6000],
+            0xE000..=0xFFFF => self.bios[address as usize - 0xE000],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | value as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | (value as u16) << 8,
+            0x4022 => {
+                self.irq_repeat = value & 0b01 != 0;
+                self.irq_enabled = value & 0b10 != 0 && self.disk_registers_enabled;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_registers_enabled = value & 0b1 != 0;
+                if !self.disk_registers_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_registers_enabled => {
+                self.write_data = value;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_registers_enabled => {
+                self.motor_on = value & 0b0000_0001 != 0;
+                self.reset_transfer = value & 0b0000_0010 != 0;
+                self.read_mode = value & 0b0000_0100 != 0;
+                self.mirroring = if value & 0b0000_1000 != 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+                self.crc_control = value & 0b0001_0000 != 0;
+                self.disk_ready = value & 0b0100_0000 != 0;
+                self.disk_irq_enabled = value & 0b1000_0000 != 0;
+                self.disk_irq = false;
+            }
+            0x6000..=0xDFFF => self.prg_ram[address as usize - 0x6000] = value,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr_ram[address as usize & 0x1FFF]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        self.chr_ram[address as usize & 0x1FFF] = value;
+    }
+
+    fn save_data(&self) -> &[u8] {
+        &self.disk
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        &mut self.disk
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    fn disk(sides: usize) -> Cartridge {
+        let mut prg_rom = vec![];
+        for _ in 0..sides {
+            let mut side = vec![0x01];
+            side.extend(b"*NINTENDO-HVC*");
+            side.resize(56, 0);
+            side.extend([0x02, 0x01]);
+            side.resize(SIDE_SIZE, 0);
+            prg_rom.extend(side);
+        }
+        Cartridge {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 20,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: true,
+            prg_ram_size: 0x8000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0x2000,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        }
+    }
+
+    fn next_byte(fds: &mut Fds) -> u8 {
+        for _ in 0..HEAD_RETURN_CYCLES + (LEAD_IN_GAP as u32 + 1) * (BYTE_CYCLES + 1) {
+            fds.cpu_clock();
+            if fds.cpu_read(0x4030) & 0b10 != 0 {
+                return fds.cpu_read(0x4031);
+            }
+        }
+        panic!("no byte transferred");
+    }
+
+    #[test]
+    fn test_timer_irq() {
+        let mut fds = Fds::new(&disk(1), &[0; BIOS_SIZE]).unwrap();
+        fds.cpu_write(0x4023, 1);
+        fds.cpu_write(0x4020, 10);
+        fds.cpu_write(0x4021, 0);
+        fds.cpu_write(0x4022, 0b11);
+        for _ in 0..10 {
+            fds.cpu_clock();
+        }
+        assert!(!fds.irq_pending());
+        fds.cpu_clock();
+        assert!(fds.irq_pending());
+        assert_eq!(fds.cpu_read(0x4030) & 1, 1);
+        assert!(!fds.irq_pending());
+        for _ in 0..11 {
+            fds.cpu_clock();
+        }
+        assert!(fds.irq_pending());
+    }
+
+    #[test]
+    fn test_reads_blocks_after_gap() {
+        let mut fds = Fds::new(&disk(2), &[0; BIOS_SIZE]).unwrap();
+        fds.cpu_write(0x4023, 1);
+        assert_eq!(fds.cpu_read(0x4032) & 0b111, 0b010);
+        // motor on, read mode, look for the block start
+        fds.cpu_write(0x4025, 0b0110_0101);
+        assert_eq!(next_byte(&mut fds), BLOCK_START_MARK);
+        assert_eq!(next_byte(&mut fds), 0x01);
+        assert_eq!(next_byte(&mut fds), b'*');
+        assert_eq!(fds.cpu_read(0x4032) & 0b111, 0);
+        assert_eq!(fds.save_data().len(), 2 * RAW_SIDE_SIZE);
+
+        fds.insert_side(None).unwrap();
+        assert_eq!(fds.cpu_read(0x4032) & 0b111, 0b111);
+        assert!(fds.insert_side(Some(2)).is_err());
+        fds.insert_side(Some(1)).unwrap();
+        assert_eq!(fds.side(), Some(1));
+    }
+
+    #[test]
+    fn test_prg_ram_and_bios() {
+        let mut bios = vec![0; BIOS_SIZE];
+        bios[BIOS_SIZE - 4] = 0x24;
+        let mut fds = Fds::new(&disk(1), &bios).unwrap();
+        fds.cpu_write(0x6000, 0x11);
+        fds.cpu_write(0xDFFF, 0x22);
+        assert_eq!(fds.cpu_read(0x6000), 0x11);
+        assert_eq!(fds.cpu_read(0xDFFF), 0x22);
+        assert_eq!(fds.cpu_read(0xFFFC), 0x24);
+        assert!(Fds::new(&disk(1), &[0; 16]).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+/// SUROM and friends: CHR bank bit 4 picks the 256KB half of a 512KB PRG ROM
+const PRG_OUTER_BANK_SIZE: usize = 0x40000;
+
+/// Mapper 1 (MMC1, SxROM boards) https://www.nesdev.org/wiki/MMC1
+///
+/// Registers are loaded one bit at a time through a 5 bit shift register. The control register
+/// switches mirroring at run time, which many games do between screens.
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    shift: u8,
+    shift_count: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+
+    /// The board ignores a write on the cycle right after another one (read-modify-write instructions)
+    cycle: u64,
+    last_write_cycle: Option<u64>,
+}
+
+impl Mmc1 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let (chr, chr_is_ram) = super::chr_memory(cartridge);
+        Mmc1 {
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; std::cmp::max(super::prg_ram_size(cartridge), 0x2000)],
+            chr,
+            chr_is_ram,
+            shift: 0,
+            shift_count: 0,
+            // PRG mode 3 at power on, so the reset vector is in the fixed last bank
+            control: 0b0_11_00,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+            cycle: 0,
+            last_write_cycle: None,
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_banks[0] = value,
+            0xC000..=0xDFFF => self.chr_banks[1] = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let offset = address as usize - 0x8000;
+        let outer = if self.prg_rom.len() > PRG_OUTER_BANK_SIZE {
+            (self.chr_banks[0] as usize >> 4 & 1) * PRG_OUTER_BANK_SIZE
+        } else {
+            0
+        };
+        let bank_count = std::cmp::min(self.prg_rom.len(), PRG_OUTER_BANK_SIZE) / PRG_BANK_SIZE;
+        let selected = (self.prg_bank & 0b1111) as usize;
+        let bank = match ((self.control >> 2) & 0b11, offset / PRG_BANK_SIZE) {
+            // 32KB mode ignores the low bit of the bank number
+            (0 | 1, slot) => (selected & !1) + slot,
+            (2, 0) => 0,
+            (2, _) => selected,
+            (_, 0) => selected,
+            (_, _) => bank_count - 1,
+        };
+        (outer + (bank % bank_count) * PRG_BANK_SIZE + (offset & (PRG_BANK_SIZE - 1))) % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let address = address as usize;
+        let bank = if self.control & 0b1_00_00 == 0 {
+            // 8KB mode ignores the low bit of the bank number
+            (self.chr_banks[0] as usize & !1) + (address >> 12)
+        } else {
+            self.chr_banks[address >> 12] as usize
+        };
+        (bank * CHR_BANK_SIZE + (address & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.prg_bank & 0b1_0000 == 0
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[(address as usize - 0x6000) % self.prg_ram.len()],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(address as usize - 0x6000) % len] = value;
+            }
+            0x8000..=0xFFFF => {
+                let consecutive = matches!(self.last_write_cycle, Some(last) if self.cycle <= last + 1);
+                self.last_write_cycle = Some(self.cycle);
+                if consecutive {
+                    return;
+                }
+                if value & 0b1000_0000 != 0 {
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= 0b0_11_00;
+                    return;
+                }
+                self.shift |= (value & 1) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    self.write_register(address, self.shift);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = value;
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.cycle += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    fn cartridge(prg_banks: usize) -> Cartridge {
+        Cartridge {
+            prg_rom: (0..prg_banks)
+                .flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE])
+                .collect(),
+            chr_rom: (0..32).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect(),
+            mapper: 1,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        }
+    }
+
+    fn load(mapper: &mut Mmc1, address: u16, value: u8) {
+        for bit in 0..5 {
+            mapper.cpu_write(address, value >> bit & 1);
+            mapper.cpu_clock();
+            mapper.cpu_clock();
+        }
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        let mut mapper = Mmc1::new(&cartridge(8));
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+        load(&mut mapper, 0xE000, 5);
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.cpu_read(0xC000), 7);
+
+        load(&mut mapper, 0x8000, 0b0_10_00);
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        assert_eq!(mapper.cpu_read(0xC000), 5);
+
+        load(&mut mapper, 0x8000, 0b0_00_00);
+        assert_eq!(mapper.cpu_read(0x8000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 5);
+    }
+
+    #[test]
+    fn test_chr_modes_and_mirroring() {
+        let mut mapper = Mmc1::new(&cartridge(8));
+        load(&mut mapper, 0xA000, 3);
+        load(&mut mapper, 0xC000, 9);
+        assert_eq!(mapper.chr_read(0x0000), 2);
+        assert_eq!(mapper.chr_read(0x1000), 3);
+
+        load(&mut mapper, 0x8000, 0b1_11_10);
+        assert_eq!(mapper.chr_read(0x0000), 3);
+        assert_eq!(mapper.chr_read(0x1000), 9);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        load(&mut mapper, 0x8000, 0b1_11_01);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn test_reset_and_consecutive_writes() {
+        let mut mapper = Mmc1::new(&cartridge(8));
+        mapper.cpu_write(0xE000, 1);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        mapper.cpu_write(0x8000, 0x80);
+        mapper.cpu_clock();
+        mapper.cpu_clock();
+        load(&mut mapper, 0xE000, 2);
+        assert_eq!(mapper.cpu_read(0x8000), 2);
+
+        // the second write of a read-modify-write instruction is dropped
+        mapper.cpu_write(0xE000, 1);
+        mapper.cpu_clock();
+        mapper.cpu_write(0xE000, 1);
+        for _ in 0..4 {
+            mapper.cpu_clock();
+            mapper.cpu_clock();
+            mapper.cpu_write(0xE000, 0);
+        }
+        assert_eq!(mapper.cpu_read(0x8000), 1);
+    }
+
+    #[test]
+    fn test_512k_prg() {
+        let mut mapper = Mmc1::new(&cartridge(32));
+        assert_eq!(mapper.cpu_read(0xC000), 15);
+        load(&mut mapper, 0xA000, 0b1_0000);
+        assert_eq!(mapper.cpu_read(0x8000), 16);
+        assert_eq!(mapper.cpu_read(0xC000), 31);
+    }
+}
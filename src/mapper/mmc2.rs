@@ -0,0 +1,187 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x1000;
+const LATCH_FD: u8 = 0xFD;
+const LATCH_FE: u8 = 0xFE;
+
+/// Mapper 9 (MMC2, Punch-Out!!) and mapper 10 (MMC4, Fire Emblem).
+///
+/// Each pattern table half has two CHR banks, picked by a latch that flips when the PPU
+/// fetches tile $FD or $FE from that half. https://www.nesdev.org/wiki/MMC2
+pub struct Mmc2 {
+    mmc4: bool,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    prg_bank: u8,
+    /// [$0000 FD, $0000 FE, $1000 FD, $1000 FE]
+    chr_banks: [u8; 4],
+    latches: [u8; 2],
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let mmc4 = cartridge.mapper == 10;
+        let prg_ram_size = if mmc4 { std::cmp::max(super::prg_ram_size(cartridge), 0x2000) } else { 0 };
+        Mmc2 {
+            mmc4,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; prg_ram_size],
+            chr: super::chr_memory(cartridge).0,
+            prg_bank: 0,
+            chr_banks: [0; 4],
+            latches: [LATCH_FE, LATCH_FE],
+            mirroring: cartridge.mirroring,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let offset = address as usize - 0x8000;
+        if self.mmc4 {
+            // 16KB switchable at $8000, last 16KB fixed at $C000
+            let bank_count = (self.prg_rom.len() / (PRG_BANK_SIZE * 2)).max(1);
+            let bank = if offset < 0x4000 { self.prg_bank as usize % bank_count } else { bank_count - 1 };
+            bank * PRG_BANK_SIZE * 2 + (offset & 0x3FFF)
+        } else {
+            // 8KB switchable at $8000, last three 8KB fixed (wrapping around PRG ROMs under 32KB)
+            let bank_count = (self.prg_rom.len() / PRG_BANK_SIZE).max(1);
+            let bank = match offset / PRG_BANK_SIZE {
+                0 => self.prg_bank as usize % bank_count,
+                slot => (bank_count * 4 + slot - 4) % bank_count,
+            };
+            bank * PRG_BANK_SIZE + (offset & (PRG_BANK_SIZE - 1))
+        }
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let half = (address as usize >> 12) & 1;
+        let register = half * 2 + if self.latches[half] == LATCH_FD { 0 } else { 1 };
+        (self.chr_banks[register] as usize * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+
+    fn update_latch(&mut self, address: u16) {
+        let address = address & 0x1FFF;
+        let half = (address >> 12) as usize;
+        // MMC2 only reacts to $0FD8/$0FE8 on the left pattern table, MMC4 to the whole 8 byte range like the right one
+        let exact_only = half == 0 && !self.mmc4;
+        match address & 0x0FF8 {
+            0x0FD8 if !exact_only || address == 0x0FD8 => self.latches[half] = LATCH_FD,
+            0x0FE8 if !exact_only || address == 0x0FE8 => self.latches[half] = LATCH_FE,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[address as usize - 0x6000] = value,
+            0xA000..=0xAFFF => self.prg_bank = value & 0b1111,
+            0xB000..=0xEFFF => self.chr_banks[(address as usize - 0xB000) >> 12] = value & 0b1_1111,
+            0xF000..=0xFFFF => {
+                self.mirroring = if value & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        // the latch flips after the fetch, so the triggering tile still comes from the old bank
+        let value = self.chr[self.chr_offset(address)];
+        self.update_latch(address);
+        value
+    }
+
+    fn chr_write(&mut self, _address: u16, _value: u8) {}
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    fn cartridge(mapper: u16) -> Cartridge {
+        Cartridge {
+            prg_rom: (0..16).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: (0..32).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect(),
+            mapper,
+            submapper: 0,
+            mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        }
+    }
+
+    #[test]
+    fn test_mmc2_prg_layout() {
+        let mut mapper = Mmc2::new(&cartridge(9));
+        mapper.cpu_write(0xA000, 5);
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+        assert_eq!(mapper.cpu_read(0xA000), 13);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+    }
+
+    #[test]
+    fn test_mmc2_small_prg_wraps() {
+        let mut cartridge = cartridge(9);
+        cartridge.prg_rom.truncate(2 * PRG_BANK_SIZE);
+        let mut mapper = Mmc2::new(&cartridge);
+        assert_eq!(mapper.cpu_read(0xA000), 1);
+        assert_eq!(mapper.cpu_read(0xC000), 0);
+        assert_eq!(mapper.cpu_read(0xE000), 1);
+    }
+
+    #[test]
+    fn test_latch_switches_after_fetch() {
+        let mut mapper = Mmc2::new(&cartridge(9));
+        mapper.cpu_write(0xB000, 3);
+        mapper.cpu_write(0xC000, 4);
+        assert_eq!(mapper.chr_read(0x0000), 4);
+        assert_eq!(mapper.chr_read(0x0FD8), 4);
+        assert_eq!(mapper.chr_read(0x0000), 3);
+        // MMC2 ignores the rest of the tile row on the left pattern table
+        mapper.chr_read(0x0FE9);
+        assert_eq!(mapper.chr_read(0x0000), 3);
+    }
+
+    #[test]
+    fn test_mmc4_latch_range() {
+        let mut mapper = Mmc2::new(&cartridge(10));
+        mapper.cpu_write(0xB000, 3);
+        mapper.cpu_write(0xC000, 4);
+        mapper.chr_read(0x0FDB);
+        assert_eq!(mapper.chr_read(0x0000), 3);
+        mapper.cpu_write(0xA000, 2);
+        assert_eq!(mapper.cpu_read(0x8000), 4);
+        assert_eq!(mapper.cpu_read(0xC000), 14);
+    }
+}
@@ -0,0 +1,430 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+const MMC6_PRG_RAM_SIZE: usize = 0x400;
+/// A12 has to stay low for this many M2 cycles before a rise clocks the IRQ counter,
+/// which filters out the quick toggles between sprite pattern and garbage nametable fetches
+const A12_FILTER_CYCLES: u8 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variant {
+    /// MMC3B/C (Sharp), IRQ fires on every clock while the counter is 0
+    Mmc3,
+    /// MMC3A (NEC), submapper 4: IRQ fires only when the counter transitions to 0
+    Mmc3A,
+    /// MMC6 (StarTropics), submapper 1: 1KB of PRG RAM with per-half protection
+    Mmc6,
+    /// TxSROM (mapper 118): CHR bank bit 7 drives CIRAM A10 instead of the mirroring register
+    TxSRom,
+    /// TQROM (mapper 119): CHR bank bit 6 selects 8KB of CHR RAM instead of CHR ROM
+    TqRom,
+}
+
+/// Mapper 4 (MMC3, MMC6) and its TxSROM/TQROM relatives https://www.nesdev.org/wiki/MMC3
+pub struct Mmc3 {
+    variant: Variant,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    chr_ram: Vec<u8>,
+
+    bank_select: u8,
+    registers: [u8; 8],
+    mirroring: Mirroring,
+    four_screen: bool,
+    prg_ram_enabled: bool,
+    prg_ram_write_protect: bool,
+    mmc6_protect: u8,
+
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_high: bool,
+    a12_low_cycles: u8,
+}
+
+impl Mmc3 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let variant = match (cartridge.mapper, cartridge.submapper) {
+            (118, _) => Variant::TxSRom,
+            (119, _) => Variant::TqRom,
+            (_, 1) => Variant::Mmc6,
+            (_, 4) => Variant::Mmc3A,
+            _ => Variant::Mmc3,
+        };
+        let prg_ram_size = match variant {
+            Variant::Mmc6 => MMC6_PRG_RAM_SIZE,
+            _ => std::cmp::max(super::prg_ram_size(cartridge), 0x2000),
+        };
+        let (chr, chr_is_ram) = super::chr_memory(cartridge);
+        let chr_ram = if variant == Variant::TqRom { vec![0; 0x2000] } else { Vec::new() };
+        Mmc3 {
+            variant,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; prg_ram_size],
+            chr,
+            chr_is_ram,
+            chr_ram,
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: cartridge.mirroring,
+            four_screen: cartridge.mirroring == Mirroring::FourScreen,
+            prg_ram_enabled: true,
+            prg_ram_write_protect: false,
+            mmc6_protect: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_high: false,
+            a12_low_cycles: A12_FILTER_CYCLES,
+        }
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let swap_c000 = self.bank_select & 0b0100_0000 != 0;
+        let bank = match ((address as usize - 0x8000) / PRG_BANK_SIZE, swap_c000) {
+            (0, false) | (2, true) => self.registers[6] as usize & 0x3F,
+            (1, _) => self.registers[7] as usize & 0x3F,
+            (0, true) | (2, false) => bank_count - 2,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    /// Raw 1KB bank register value mapped at a pattern table address
+    fn chr_bank(&self, address: u16) -> u8 {
+        let address = if self.bank_select & 0b1000_0000 != 0 { address ^ 0x1000 } else { address };
+        match (address as usize & 0x1FFF) / CHR_BANK_SIZE {
+            0 => self.registers[0] & 0xFE,
+            1 => self.registers[0] | 1,
+            2 => self.registers[1] & 0xFE,
+            3 => self.registers[1] | 1,
+            slot => self.registers[slot - 2],
+        }
+    }
+
+    /// Returns whether the access goes to the TQROM CHR RAM and the offset inside it
+    fn chr_offset(&self, address: u16) -> (bool, usize) {
+        let bank = self.chr_bank(address);
+        let offset = address as usize & (CHR_BANK_SIZE - 1);
+        match self.variant {
+            Variant::TqRom if bank & 0b0100_0000 != 0 => (true, (bank as usize & 0b111) * CHR_BANK_SIZE + offset),
+            Variant::TxSRom => (false, ((bank & 0x7F) as usize * CHR_BANK_SIZE + offset) % self.chr.len()),
+            _ => (false, (bank as usize * CHR_BANK_SIZE + offset) % self.chr.len()),
+        }
+    }
+
+    fn mmc6_ram_enabled(&self) -> bool {
+        self.bank_select & 0b0010_0000 != 0
+    }
+
+    /// MMC6 splits its RAM in two 512 byte halves ($7000-$71FF, $7200-$73FF), each with read/write enables
+    fn mmc6_access(&self, address: u16, write: bool) -> bool {
+        let high_half = address & 0x200 != 0;
+        let read_bit = if high_half { 0b1000_0000 } else { 0b0010_0000 };
+        let write_bit = if high_half { 0b0100_0000 } else { 0b0001_0000 };
+        let readable = self.mmc6_ram_enabled() && self.mmc6_protect & read_bit != 0;
+        if write {
+            readable && self.mmc6_protect & write_bit != 0
+        } else {
+            readable
+        }
+    }
+
+    fn prg_ram_read(&self, address: u16) -> u8 {
+        match self.variant {
+            Variant::Mmc6 => match address {
+                0x7000..=0x7FFF if self.mmc6_access(address, false) => {
+                    self.prg_ram[address as usize & (MMC6_PRG_RAM_SIZE - 1)]
+                }
+                _ => 0,
+            },
+            _ if self.prg_ram_enabled => self.prg_ram[(address as usize - 0x6000) % self.prg_ram.len()],
+            _ => 0,
+        }
+    }
+
+    fn prg_ram_write(&mut self, address: u16, value: u8) {
+        match self.variant {
+            Variant::Mmc6 => {
+                if address >= 0x7000 && self.mmc6_access(address, true) {
+                    self.prg_ram[address as usize & (MMC6_PRG_RAM_SIZE - 1)] = value;
+                }
+            }
+            _ => {
+                if self.prg_ram_enabled && !self.prg_ram_write_protect {
+                    let len = self.prg_ram.len();
+                    self.prg_ram[(address as usize - 0x6000) % len] = value;
+                }
+            }
+        }
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let reload = self.irq_reload;
+        let previous = self.irq_counter;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+        self.irq_reload = false;
+
+        let trigger = match self.variant {
+            Variant::Mmc3A => self.irq_counter == 0 && (previous != 0 || reload),
+            _ => self.irq_counter == 0,
+        };
+        if trigger && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF => self.prg_ram_read(address),
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        let even = address & 1 == 0;
+        match address {
+            0x6000..=0x7FFF => self.prg_ram_write(address, value),
+            0x8000..=0x9FFF if even => self.bank_select = value,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0b111) as usize] = value,
+            0xA000..=0xBFFF if even && !self.four_screen => {
+                self.mirroring = if value & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            // four-screen boards have their mirroring hardwired
+            0xA000..=0xBFFF if even => {}
+            0xA000..=0xBFFF => match self.variant {
+                Variant::Mmc6 => {
+                    if self.mmc6_ram_enabled() {
+                        self.mmc6_protect = value;
+                    }
+                }
+                _ => {
+                    self.prg_ram_enabled = value & 0b1000_0000 != 0;
+                    self.prg_ram_write_protect = value & 0b0100_0000 != 0;
+                }
+            },
+            0xC000..=0xDFFF if even => self.irq_latch = value,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        match self.chr_offset(address) {
+            (true, offset) => self.chr_ram[offset],
+            (false, offset) => self.chr[offset],
+        }
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        match self.chr_offset(address) {
+            (true, offset) => self.chr_ram[offset] = value,
+            (false, offset) if self.chr_is_ram => self.chr[offset] = value,
+            _ => {}
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn nametable_page(&self, table: usize) -> usize {
+        match self.variant {
+            Variant::TxSRom => (self.chr_bank((table as u16 & 0b11) * CHR_BANK_SIZE as u16) >> 7) as usize,
+            _ => self.mirroring.nametable_page(table),
+        }
+    }
+
+    fn ppu_address(&mut self, address: u16) {
+        let a12 = address & 0x1000 != 0;
+        if a12 && !self.a12_high && self.a12_low_cycles >= A12_FILTER_CYCLES {
+            self.clock_irq_counter();
+        }
+        if !a12 && self.a12_high {
+            self.a12_low_cycles = 0;
+        }
+        self.a12_high = a12;
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.a12_high {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    fn cartridge(mapper: u16, submapper: u8) -> Cartridge {
+        Cartridge {
+            prg_rom: (0..32).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: (0..128).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect(),
+            mapper,
+            submapper,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        }
+    }
+
+    /// Simulates one scanline worth of PPU fetches: background at $0xxx, sprites at $1xxx
+    fn scanline(mapper: &mut Mmc3) {
+        for _ in 0..85 {
+            mapper.ppu_address(0x0000);
+            mapper.cpu_clock();
+        }
+        for _ in 0..8 {
+            mapper.ppu_address(0x2000);
+            mapper.ppu_address(0x1000);
+        }
+        for _ in 0..28 {
+            mapper.cpu_clock();
+        }
+    }
+
+    #[test]
+    fn test_prg_banking_modes() {
+        let mut mapper = Mmc3::new(&cartridge(4, 0));
+        mapper.cpu_write(0x8000, 6);
+        mapper.cpu_write(0x8001, 3);
+        mapper.cpu_write(0x8000, 7);
+        mapper.cpu_write(0x8001, 5);
+        assert_eq!(mapper.cpu_read(0x8000), 3);
+        assert_eq!(mapper.cpu_read(0xA000), 5);
+        assert_eq!(mapper.cpu_read(0xC000), 30);
+        assert_eq!(mapper.cpu_read(0xE000), 31);
+
+        mapper.cpu_write(0x8000, 0b0100_0000);
+        assert_eq!(mapper.cpu_read(0x8000), 30);
+        assert_eq!(mapper.cpu_read(0xC000), 3);
+    }
+
+    #[test]
+    fn test_chr_inversion() {
+        let mut mapper = Mmc3::new(&cartridge(4, 0));
+        mapper.cpu_write(0x8000, 0);
+        mapper.cpu_write(0x8001, 9);
+        mapper.cpu_write(0x8000, 2);
+        mapper.cpu_write(0x8001, 40);
+        assert_eq!(mapper.chr_read(0x0000), 8);
+        assert_eq!(mapper.chr_read(0x0400), 9);
+        assert_eq!(mapper.chr_read(0x1000), 40);
+
+        mapper.cpu_write(0x8000, 0b1000_0000);
+        assert_eq!(mapper.chr_read(0x0000), 40);
+        assert_eq!(mapper.chr_read(0x1400), 9);
+    }
+
+    #[test]
+    fn test_mirroring_and_prg_ram_protect() {
+        let mut mapper = Mmc3::new(&cartridge(4, 0));
+        mapper.cpu_write(0xA000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+
+        mapper.cpu_write(0xA001, 0b1000_0000);
+        mapper.cpu_write(0x6000, 0x42);
+        assert_eq!(mapper.cpu_read(0x6000), 0x42);
+        mapper.cpu_write(0xA001, 0b1100_0000);
+        mapper.cpu_write(0x6000, 0x13);
+        assert_eq!(mapper.cpu_read(0x6000), 0x42);
+    }
+
+    #[test]
+    fn test_scanline_irq() {
+        let mut mapper = Mmc3::new(&cartridge(4, 0));
+        mapper.cpu_write(0xC000, 2);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+
+        scanline(&mut mapper);
+        scanline(&mut mapper);
+        assert!(!mapper.irq_pending());
+        scanline(&mut mapper);
+        assert!(mapper.irq_pending());
+
+        mapper.cpu_write(0xE000, 0);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_mmc3a_latch_zero_fires_once() {
+        let mut mapper = Mmc3::new(&cartridge(4, 4));
+        mapper.cpu_write(0xC000, 0);
+        mapper.cpu_write(0xC001, 0);
+        mapper.cpu_write(0xE001, 0);
+        scanline(&mut mapper);
+        assert!(mapper.irq_pending());
+        mapper.cpu_write(0xE000, 0);
+        mapper.cpu_write(0xE001, 0);
+        scanline(&mut mapper);
+        assert!(!mapper.irq_pending());
+    }
+
+    #[test]
+    fn test_mmc6_ram_halves() {
+        let mut mapper = Mmc3::new(&cartridge(4, 1));
+        mapper.cpu_write(0x8000, 0b0010_0000);
+        mapper.cpu_write(0xA001, 0b0011_0000);
+        mapper.cpu_write(0x7000, 0x11);
+        mapper.cpu_write(0x7200, 0x22);
+        assert_eq!(mapper.cpu_read(0x7400), 0x11);
+        assert_eq!(mapper.cpu_read(0x7200), 0);
+    }
+
+    #[test]
+    fn test_txsrom_nametables_follow_chr_banks() {
+        let mut mapper = Mmc3::new(&cartridge(118, 0));
+        mapper.cpu_write(0x8000, 0);
+        mapper.cpu_write(0x8001, 0x80);
+        mapper.cpu_write(0x8000, 1);
+        mapper.cpu_write(0x8001, 0x00);
+        assert_eq!(mapper.nametable_page(0), 1);
+        assert_eq!(mapper.nametable_page(1), 1);
+        assert_eq!(mapper.nametable_page(2), 0);
+    }
+}
@@ -0,0 +1,604 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const EXRAM_SIZE: usize = 0x400;
+const MIN_PRG_RAM_SIZE: usize = 0x10000;
+
+/// Nametable/attribute fetches per scanline after the scanline is detected:
+/// tiles 2-33 (64 fetches), then 16 garbage fetches while sprite patterns are loaded
+const BG_FETCHES: u8 = 64;
+const SPRITE_FETCHES_END: u8 = 80;
+/// The PPU is considered idle (not rendering) when the bus stays quiet for this many CPU cycles
+const IDLE_CYCLES: u8 = 3;
+
+/// Mapper 5: Nintendo MMC5 https://www.nesdev.org/wiki/MMC5
+///
+/// The board follows the PPU fetch pattern on the address bus to find scanlines, tell background fetches
+/// from sprite fetches (separate CHR bank sets in 8x16 mode) and track the current tile for extended
+/// attributes and the vertical split. The expansion audio registers are accepted and ignored.
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    exram: [u8; EXRAM_SIZE],
+
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2],
+    exram_mode: u8,
+    nametable_mapping: u8,
+    fill_tile: u8,
+    fill_attribute: u8,
+    /// $5113-$5117
+    prg_banks: [u8; 5],
+    /// $5120-$5127, used for sprites in 8x16 mode
+    chr_banks_a: [u16; 8],
+    /// $5128-$512B, used for the background in 8x16 mode
+    chr_banks_b: [u16; 4],
+    chr_upper: u8,
+    last_chr_set_b: bool,
+
+    split_control: u8,
+    split_scroll: u8,
+    split_bank: u8,
+
+    irq_target: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    multiplicand: u8,
+    multiplier: u8,
+
+    sprite_8x16: bool,
+    rendering_enabled: bool,
+    in_frame: bool,
+    scanline: u8,
+    last_address: u16,
+    address_matches: u8,
+    fetch_count: u8,
+    idle_cycles: u8,
+    exram_latch: u8,
+}
+
+impl Mmc5 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let (chr, chr_is_ram) = super::chr_memory(cartridge);
+        Mmc5 {
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; std::cmp::max(super::prg_ram_size(cartridge), MIN_PRG_RAM_SIZE)],
+            chr,
+            chr_is_ram,
+            exram: [0; EXRAM_SIZE],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0xFF, 0xFF, 0xFF, 0xFF],
+            chr_banks_a: [0; 8],
+            chr_banks_b: [0; 4],
+            chr_upper: 0,
+            last_chr_set_b: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_target: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            sprite_8x16: false,
+            rendering_enabled: false,
+            in_frame: false,
+            scanline: 0,
+            last_address: 0,
+            address_matches: 0,
+            fetch_count: 0,
+            idle_cycles: 0,
+            exram_latch: 0,
+        }
+    }
+
+    /// Returns the byte offset and whether it points into PRG RAM rather than ROM
+    fn prg_offset(&self, address: u16) -> (usize, bool) {
+        if address < 0x8000 {
+            let bank = (self.prg_banks[0] & 0x0F) as usize;
+            return ((bank * PRG_BANK_SIZE + (address as usize & 0x1FFF)) % self.prg_ram.len(), true);
+        }
+        let slot = (address as usize - 0x8000) / PRG_BANK_SIZE;
+        // (register, low bank bits taken from the slot instead of the register)
+        let (register, slot_bits) = match (self.prg_mode, slot) {
+            (0, _) => (4, 0b11),
+            (1, 0 | 1) | (2, 0 | 1) => (2, 0b01),
+            (1, _) => (4, 0b01),
+            (2, 2) => (3, 0),
+            (2, _) => (4, 0),
+            (_, slot) => (1 + slot, 0),
+        };
+        let value = self.prg_banks[register];
+        let bank = (value as usize & 0x7F & !slot_bits) | (slot & slot_bits);
+        let offset = address as usize & 0x1FFF;
+        if register == 4 || value & 0x80 != 0 {
+            ((bank * PRG_BANK_SIZE + offset) % self.prg_rom.len(), false)
+        } else {
+            (((bank & 0x0F) * PRG_BANK_SIZE + offset) % self.prg_ram.len(), true)
+        }
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect[0] & 0b11 == 0b10 && self.prg_ram_protect[1] & 0b11 == 0b01
+    }
+
+    fn rendering(&self) -> bool {
+        self.in_frame && self.rendering_enabled
+    }
+
+    fn sprite_fetch(&self) -> bool {
+        self.fetch_count >= BG_FETCHES && self.fetch_count < SPRITE_FETCHES_END
+    }
+
+    fn background_fetch(&self) -> bool {
+        self.rendering() && !self.sprite_fetch()
+    }
+
+    /// Screen column of the background tile currently being fetched, and whether it belongs to the next scanline
+    fn fetch_tile(&self) -> (u8, bool) {
+        if self.fetch_count < BG_FETCHES {
+            (self.fetch_count / 2 + 2, false)
+        } else {
+            ((self.fetch_count - SPRITE_FETCHES_END) / 2, true)
+        }
+    }
+
+    fn in_split(&self) -> bool {
+        if self.split_control & 0b1000_0000 == 0 || !self.background_fetch() || self.exram_mode >= 2 {
+            return false;
+        }
+        let (tile, _) = self.fetch_tile();
+        let threshold = self.split_control & 0b1_1111;
+        if self.split_control & 0b0100_0000 != 0 {
+            tile >= threshold
+        } else {
+            tile < threshold
+        }
+    }
+
+    fn split_y(&self) -> usize {
+        let (_, next_line) = self.fetch_tile();
+        let line = self.scanline as usize + if next_line { 1 } else { 0 };
+        (self.split_scroll as usize + line) % 240
+    }
+
+    fn split_nametable_read(&self, address: u16) -> u8 {
+        let (tile, _) = self.fetch_tile();
+        let tile = (tile & 0x1F) as usize;
+        let y = self.split_y();
+        if address & 0x3FF >= 0x3C0 {
+            let attribute = self.exram[0x3C0 + (y / 32) * 8 + tile / 4];
+            let shift = ((y / 16) & 1) * 4 + ((tile / 2) & 1) * 2;
+            let palette = (attribute >> shift) & 0b11;
+            palette * 0b0101_0101
+        } else {
+            self.exram[(y / 8) * 32 + tile]
+        }
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let address = address as usize & 0x1FFF;
+        let use_set_b = if self.rendering() && self.sprite_8x16 {
+            !self.sprite_fetch()
+        } else {
+            self.last_chr_set_b
+        };
+
+        let size = 0x2000 >> self.chr_mode;
+        let bank = if use_set_b {
+            // the B set only covers 4KB, mirrored into both pattern tables
+            let registers_per_slot = 4 >> self.chr_mode.saturating_sub(1);
+            let slot = (address & 0xFFF) / std::cmp::min(size, 0x1000);
+            self.chr_banks_b[(slot + 1) * registers_per_slot - 1] as usize
+        } else {
+            let registers_per_slot = 8 >> self.chr_mode;
+            self.chr_banks_a[(address / size + 1) * registers_per_slot - 1] as usize
+        };
+        (bank * size + (address & (size - 1))) % self.chr.len()
+    }
+
+    fn detect_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            if self.scanline == self.irq_target && self.irq_target != 0 {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.irq_pending = false;
+        }
+        self.fetch_count = 0;
+    }
+
+    fn leave_frame(&mut self) {
+        self.in_frame = false;
+        self.address_matches = 0;
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x5204 => {
+                let status = (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6;
+                self.irq_pending = false;
+                status
+            }
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[address as usize - 0x5C00],
+            0x6000..=0xFFFF => {
+                if address == 0xFFFA || address == 0xFFFB {
+                    // NMI vector fetch marks the end of the frame
+                    self.leave_frame();
+                }
+                match self.prg_offset(address) {
+                    (offset, true) => self.prg_ram[offset],
+                    (offset, false) => self.prg_rom[offset],
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    /// Skips the NMI vector frame detection and the IRQ acknowledge of $5204
+    fn peek(&mut self, address: u16) -> u8 {
+        match address {
+            0x5204 => (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6,
+            0x6000..=0xFFFF => match self.prg_offset(address) {
+                (offset, true) => self.prg_ram[offset],
+                (offset, false) => self.prg_rom[offset],
+            },
+            _ => self.cpu_read(address),
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x5100 => self.prg_mode = value & 0b11,
+            0x5101 => self.chr_mode = value & 0b11,
+            0x5102 => self.prg_ram_protect[0] = value,
+            0x5103 => self.prg_ram_protect[1] = value,
+            0x5104 => self.exram_mode = value & 0b11,
+            0x5105 => self.nametable_mapping = value,
+            0x5106 => self.fill_tile = value,
+            0x5107 => self.fill_attribute = value & 0b11,
+            0x5113..=0x5117 => self.prg_banks[address as usize - 0x5113] = value,
+            0x5120..=0x5127 => {
+                self.chr_banks_a[address as usize - 0x5120] = (self.chr_upper as u16) << 8 | value as u16;
+                self.last_chr_set_b = false;
+            }
+            0x5128..=0x512B => {
+                self.chr_banks_b[address as usize - 0x5128] = (self.chr_upper as u16) << 8 | value as u16;
+                self.last_chr_set_b = true;
+            }
+            0x5130 => self.chr_upper = value & 0b11,
+            0x5200 => self.split_control = value,
+            0x5201 => self.split_scroll = value,
+            0x5202 => self.split_bank = value,
+            0x5203 => self.irq_target = value,
+            0x5204 => self.irq_enabled = value & 0b1000_0000 != 0,
+            0x5205 => self.multiplicand = value,
+            0x5206 => self.multiplier = value,
+            0x5C00..=0x5FFF => {
+                let offset = address as usize - 0x5C00;
+                match self.exram_mode {
+                    // as nametable/attribute memory the CPU can only write while the PPU is rendering
+                    0 | 1 => self.exram[offset] = if self.in_frame { value } else { 0 },
+                    2 => self.exram[offset] = value,
+                    _ => {}
+                }
+            }
+            0x6000..=0xDFFF if self.prg_ram_writable() => {
+                if let (offset, true) = self.prg_offset(address) {
+                    self.prg_ram[offset] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        if self.in_split() {
+            let y = self.split_y();
+            let offset = self.split_bank as usize * 0x1000 + (address as usize & 0xFF8) + (y & 0b111);
+            return self.chr[offset % self.chr.len()];
+        }
+        if self.exram_mode == 1 && self.background_fetch() {
+            let bank = (self.chr_upper as usize) << 6 | (self.exram_latch & 0x3F) as usize;
+            return self.chr[(bank * 0x1000 + (address as usize & 0xFFF)) % self.chr.len()];
+        }
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = value;
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.nametable_mapping {
+            0x44 => Mirroring::Vertical,
+            0x50 => Mirroring::Horizontal,
+            0x55 => Mirroring::SingleScreenUpper,
+            _ => Mirroring::SingleScreenLower,
+        }
+    }
+
+    fn nametable_page(&self, table: usize) -> usize {
+        ((self.nametable_mapping >> ((table & 0b11) * 2)) & 1) as usize
+    }
+
+    fn nametable_read(&mut self, address: u16) -> Option<u8> {
+        let attribute = address & 0x3FF >= 0x3C0;
+        if self.in_split() {
+            return Some(self.split_nametable_read(address));
+        }
+        if self.exram_mode == 1 && self.background_fetch() {
+            if attribute {
+                return Some((self.exram_latch >> 6) * 0b0101_0101);
+            }
+            self.exram_latch = self.exram[address as usize & 0x3FF];
+        }
+
+        let table = ((address >> 10) & 0b11) as usize;
+        match (self.nametable_mapping >> (table * 2)) & 0b11 {
+            2 if self.exram_mode < 2 => Some(self.exram[address as usize & 0x3FF]),
+            2 => Some(0),
+            3 if attribute => Some(self.fill_attribute * 0b0101_0101),
+            3 => Some(self.fill_tile),
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&mut self, address: u16, value: u8) -> bool {
+        let table = ((address >> 10) & 0b11) as usize;
+        match (self.nametable_mapping >> (table * 2)) & 0b11 {
+            2 => {
+                if self.exram_mode < 2 {
+                    self.exram[address as usize & 0x3FF] = value;
+                }
+                true
+            }
+            3 => true,
+            _ => false,
+        }
+    }
+
+    fn ppu_register_write(&mut self, address: u16, value: u8) {
+        match address {
+            0x2000 => self.sprite_8x16 = value & 0b0010_0000 != 0,
+            0x2001 => {
+                self.rendering_enabled = value & 0b0001_1000 != 0;
+                if !self.rendering_enabled {
+                    self.leave_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_address(&mut self, address: u16) {
+        self.idle_cycles = 0;
+        if let 0x2000..=0x2FFF = address {
+            self.fetch_count = self.fetch_count.saturating_add(1);
+            // the two dummy nametable fetches at the end of a line plus the first fetch of the next one
+            if address == self.last_address {
+                self.address_matches += 1;
+                if self.address_matches == 2 {
+                    self.detect_scanline();
+                }
+            } else {
+                self.address_matches = 0;
+            }
+        } else {
+            self.address_matches = 0;
+        }
+        self.last_address = address;
+    }
+
+    fn cpu_clock(&mut self) {
+        if self.in_frame {
+            self.idle_cycles += 1;
+            if self.idle_cycles >= IDLE_CYCLES {
+                self.leave_frame();
+            }
+        }
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    fn cartridge() -> Cartridge {
+        Cartridge {
+            prg_rom: (0..64).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: (0..256).flat_map(|bank| vec![bank as u8; 0x400]).collect(),
+            mapper: 5,
+            submapper: 0,
+            mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        }
+    }
+
+    fn start_frame(mapper: &mut Mmc5) {
+        mapper.ppu_address(0x2002);
+        mapper.ppu_address(0x2002);
+    }
+
+    /// Feeds the fetch pattern of one rendered scanline: 32 background tiles, 8 sprites, 2 prefetched tiles
+    /// and the two dummy nametable reads that let the MMC5 find the next scanline
+    fn scanline(mapper: &mut Mmc5) {
+        let tile = |mapper: &mut Mmc5, index: u16| {
+            mapper.ppu_address(0x2000 + index);
+            mapper.nametable_read(0x2000 + index);
+            mapper.ppu_address(0x23C0);
+            mapper.nametable_read(0x23C0);
+            mapper.ppu_address(0x0000);
+            mapper.chr_read(0x0000);
+            mapper.ppu_address(0x0008);
+            mapper.chr_read(0x0008);
+        };
+        for index in 2..34 {
+            tile(mapper, index);
+        }
+        for _ in 0..8 {
+            mapper.ppu_address(0x2000);
+            mapper.ppu_address(0x2000);
+            mapper.ppu_address(0x1000);
+            mapper.ppu_address(0x1008);
+        }
+        tile(mapper, 0);
+        tile(mapper, 1);
+        mapper.ppu_address(0x2002);
+        mapper.ppu_address(0x2002);
+    }
+
+    #[test]
+    fn test_prg_modes() {
+        let mut mapper = Mmc5::new(&cartridge());
+        assert_eq!(mapper.cpu_read(0xE000), 63);
+        mapper.cpu_write(0x5100, 3);
+        mapper.cpu_write(0x5114, 0x85);
+        assert_eq!(mapper.cpu_read(0x8000), 5);
+
+        mapper.cpu_write(0x5100, 1);
+        mapper.cpu_write(0x5115, 0x87);
+        assert_eq!(mapper.cpu_read(0x8000), 6);
+        assert_eq!(mapper.cpu_read(0xA000), 7);
+
+        // RAM mapped at $8000 in 16KB mode, writable once both protect registers are set
+        mapper.cpu_write(0x5115, 0x02);
+        mapper.cpu_write(0x8000, 0x42);
+        assert_eq!(mapper.cpu_read(0x8000), 0);
+        mapper.cpu_write(0x5102, 0b10);
+        mapper.cpu_write(0x5103, 0b01);
+        mapper.cpu_write(0x8000, 0x42);
+        assert_eq!(mapper.cpu_read(0x8000), 0x42);
+    }
+
+    #[test]
+    fn test_multiplier() {
+        let mut mapper = Mmc5::new(&cartridge());
+        mapper.cpu_write(0x5205, 200);
+        mapper.cpu_write(0x5206, 100);
+        assert_eq!(mapper.cpu_read(0x5205), (20000u16 & 0xFF) as u8);
+        assert_eq!(mapper.cpu_read(0x5206), (20000u16 >> 8) as u8);
+    }
+
+    #[test]
+    fn test_scanline_irq_and_in_frame() {
+        let mut mapper = Mmc5::new(&cartridge());
+        mapper.ppu_register_write(0x2001, 0b0001_1000);
+        mapper.cpu_write(0x5203, 3);
+        mapper.cpu_write(0x5204, 0x80);
+
+        start_frame(&mut mapper);
+        scanline(&mut mapper);
+        assert_eq!(mapper.cpu_read(0x5204), 0b0100_0000);
+        for _ in 0..2 {
+            scanline(&mut mapper);
+        }
+        assert!(!mapper.irq_pending());
+        scanline(&mut mapper);
+        assert!(mapper.irq_pending());
+        // peeking neither acknowledges the IRQ nor ends the frame on the NMI vector
+        assert_eq!(mapper.peek(0x5204), 0b1100_0000);
+        mapper.peek(0xFFFA);
+        assert!(mapper.irq_pending());
+        assert_eq!(mapper.cpu_read(0x5204), 0b1100_0000);
+        assert!(!mapper.irq_pending());
+
+        for _ in 0..IDLE_CYCLES {
+            mapper.cpu_clock();
+        }
+        assert_eq!(mapper.cpu_read(0x5204), 0);
+    }
+
+    #[test]
+    fn test_fill_mode_and_exram_nametable() {
+        let mut mapper = Mmc5::new(&cartridge());
+        mapper.cpu_write(0x5105, 0b11_10_01_00);
+        mapper.cpu_write(0x5106, 0x24);
+        mapper.cpu_write(0x5107, 0b10);
+        assert_eq!(mapper.nametable_page(1), 1);
+        assert_eq!(mapper.nametable_read(0x2400), None);
+        assert_eq!(mapper.nametable_read(0x2C05), Some(0x24));
+        assert_eq!(mapper.nametable_read(0x2FC0), Some(0b1010_1010));
+
+        mapper.cpu_write(0x5104, 0);
+        assert!(mapper.nametable_write(0x2805, 0x99));
+        assert_eq!(mapper.nametable_read(0x2805), Some(0x99));
+    }
+
+    #[test]
+    fn test_8x16_uses_separate_chr_sets() {
+        let mut mapper = Mmc5::new(&cartridge());
+        mapper.cpu_write(0x5101, 3);
+        mapper.cpu_write(0x5120, 10);
+        mapper.cpu_write(0x5128, 20);
+        mapper.ppu_register_write(0x2000, 0b0010_0000);
+        mapper.ppu_register_write(0x2001, 0b0001_1000);
+        // outside rendering the last written set wins
+        assert_eq!(mapper.chr_read(0x0000), 20);
+
+        mapper.ppu_address(0x2000);
+        mapper.ppu_address(0x2000);
+        mapper.ppu_address(0x2000);
+        assert_eq!(mapper.chr_read(0x0000), 20);
+        for index in 1..(BG_FETCHES as u16 + 1) {
+            mapper.ppu_address(0x2000 + index);
+        }
+        assert_eq!(mapper.chr_read(0x0000), 10);
+    }
+
+    #[test]
+    fn test_extended_attributes() {
+        let mut mapper = Mmc5::new(&cartridge());
+        mapper.cpu_write(0x5104, 2);
+        mapper.cpu_write(0x5C05, 0b1100_0011);
+        mapper.cpu_write(0x5104, 1);
+        mapper.ppu_register_write(0x2001, 0b0001_1000);
+        mapper.ppu_address(0x2005);
+        mapper.ppu_address(0x2005);
+        mapper.ppu_address(0x2005);
+        assert_eq!(mapper.nametable_read(0x2005), None);
+        assert_eq!(mapper.nametable_read(0x23C1), Some(0xFF));
+        // 4KB bank 3 = 1KB bank 12
+        assert_eq!(mapper.chr_read(0x0010), 12);
+    }
+}
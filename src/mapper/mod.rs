@@ -0,0 +1,115 @@
+pub mod nrom;
+pub mod axrom;
+pub mod fds;
+pub mod mmc1;
+pub mod mmc2;
+pub mod mmc3;
+pub mod mmc5;
+pub mod vrc4;
+pub mod vrc6;
+pub mod vrc7;
+mod vrc_irq;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::cartridge::{Cartridge, Mirroring};
+
+/// Cartridge board logic sitting between the CPU/PPU buses and the ROM/RAM chips.
+///
+/// The CPU side sees $4020-$FFFF, the PPU side sees the pattern tables at $0000-$1FFF.
+pub trait Mapper {
+    fn cpu_read(&mut self, address: u16) -> u8;
+    fn cpu_write(&mut self, address: u16, value: u8);
+
+    /// CPU read without side effects, for traces and debuggers. Boards whose reads change their state override it.
+    fn peek(&mut self, address: u16) -> u8 {
+        self.cpu_read(address)
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8;
+    fn chr_write(&mut self, address: u16, value: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// PRG RAM at $6000-$7FFF, persisted to a save file when the cartridge has a battery
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    /// What the save file keeps: the battery backed PRG RAM, or the disk contents for the FDS
+    fn save_data(&self) -> &[u8] {
+        self.prg_ram()
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        self.prg_ram_mut()
+    }
+
+    /// Which nametable page backs nametable `table`, for boards that route CIRAM A10 themselves.
+    /// The PPU asks on every nametable access, so mirroring changes apply right away.
+    fn nametable_page(&self, table: usize) -> usize {
+        self.mirroring().nametable_page(table)
+    }
+
+    /// Nametable reads ($2000-$2FFF), for boards that supply nametable data themselves instead of CIRAM
+    fn nametable_read(&mut self, _address: u16) -> Option<u8> {
+        None
+    }
+
+    /// Returns true when the board took the write instead of CIRAM
+    fn nametable_write(&mut self, _address: u16, _value: u8) -> bool {
+        false
+    }
+
+    /// Every address the PPU puts on its bus ($0000-$3FFF), so boards can watch address lines (e.g. A12).
+    /// Called before the matching chr_read/nametable_read.
+    fn ppu_address(&mut self, _address: u16) {}
+
+    /// CPU writes to the PPU registers ($2000-$2007), which some boards snoop to follow the PPU state
+    fn ppu_register_write(&mut self, _address: u16, _value: u8) {}
+
+    /// Called once per CPU cycle (M2)
+    fn cpu_clock(&mut self) {}
+
+    fn irq_pending(&self) -> bool {
+        false
+    }
+}
+
+pub type MapperRef = Rc<RefCell<dyn Mapper>>;
+
+pub fn create(cartridge: &Cartridge) -> Result<MapperRef, String> {
+    let mapper: MapperRef = match cartridge.mapper {
+        0 => Rc::new(RefCell::new(nrom::Nrom::new(cartridge))),
+        1 => Rc::new(RefCell::new(mmc1::Mmc1::new(cartridge))),
+        4 | 118 | 119 => Rc::new(RefCell::new(mmc3::Mmc3::new(cartridge))),
+        5 => Rc::new(RefCell::new(mmc5::Mmc5::new(cartridge))),
+        7 => Rc::new(RefCell::new(axrom::Axrom::new(cartridge))),
+        9 | 10 => Rc::new(RefCell::new(mmc2::Mmc2::new(cartridge))),
+        21 | 22 | 23 | 25 => Rc::new(RefCell::new(vrc4::Vrc4::new(cartridge))),
+        24 | 26 => Rc::new(RefCell::new(vrc6::Vrc6::new(cartridge))),
+        85 => Rc::new(RefCell::new(vrc7::Vrc7::new(cartridge))),
+        20 => return Err("FDS images need the disk system BIOS, load them with Bus::load_fds".to_string()),
+        id => return Err(format!("Mapper {} is not supported", id)),
+    };
+    Ok(mapper)
+}
+
+/// CHR RAM when the board has no CHR ROM, otherwise the CHR ROM itself
+fn chr_memory(cartridge: &Cartridge) -> (Vec<u8>, bool) {
+    if cartridge.chr_rom.is_empty() {
+        let size = cartridge.chr_ram_size + cartridge.chr_nvram_size;
+        (vec![0; std::cmp::max(size, 0x2000)], true)
+    } else {
+        (cartridge.chr_rom.clone(), false)
+    }
+}
+
+fn prg_ram_size(cartridge: &Cartridge) -> usize {
+    cartridge.prg_ram_size + cartridge.prg_nvram_size
+}
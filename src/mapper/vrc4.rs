@@ -0,0 +1,249 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Mappers 21, 22, 23 and 25: Konami VRC2 and VRC4 https://www.nesdev.org/wiki/VRC2_and_VRC4
+///
+/// The boards only differ in which CPU address lines feed the two register select pins,
+/// so every write is first translated to a canonical $x000-$x003 register.
+pub struct Vrc4 {
+    vrc2: bool,
+    /// CPU address bits wired to register select bit 0 and bit 1, several when the submapper is unknown
+    select_lines: (u16, u16),
+    /// VRC2a ignores the low bit of CHR bank numbers
+    chr_shift: u8,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    prg_banks: [u8; 2],
+    prg_swap: bool,
+    prg_ram_enabled: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    /// VRC2 boards without PRG RAM expose a single readable bit at $6000-$6FFF
+    latch: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        const A0: u16 = 1 << 0;
+        const A1: u16 = 1 << 1;
+        const A2: u16 = 1 << 2;
+        const A3: u16 = 1 << 3;
+        const A6: u16 = 1 << 6;
+        const A7: u16 = 1 << 7;
+        let (vrc2, select_lines) = match (cartridge.mapper, cartridge.submapper) {
+            (21, 1) => (false, (A1, A2)),
+            (21, 2) => (false, (A6, A7)),
+            (21, _) => (false, (A1 | A6, A2 | A7)),
+            (22, _) => (true, (A1, A0)),
+            (23, 1) => (false, (A0, A1)),
+            (23, 2) => (false, (A2, A3)),
+            (23, 3) => (true, (A0, A1)),
+            (23, _) => (false, (A0 | A2, A1 | A3)),
+            (25, 1) => (false, (A1, A0)),
+            (25, 2) => (false, (A3, A2)),
+            (25, 3) => (true, (A1, A0)),
+            (_, _) => (false, (A1 | A3, A0 | A2)),
+        };
+        let (chr, chr_is_ram) = super::chr_memory(cartridge);
+        Vrc4 {
+            vrc2,
+            select_lines,
+            chr_shift: if cartridge.mapper == 22 { 1 } else { 0 },
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; super::prg_ram_size(cartridge)],
+            chr,
+            chr_is_ram,
+            prg_banks: [0, 1],
+            prg_swap: false,
+            prg_ram_enabled: vrc2,
+            chr_banks: [0; 8],
+            mirroring: cartridge.mirroring,
+            latch: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        let bit0 = if address & self.select_lines.0 != 0 { 1 } else { 0 };
+        let bit1 = if address & self.select_lines.1 != 0 { 2 } else { 0 };
+        (address & 0xF000) | bit0 | bit1
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match ((address as usize - 0x8000) / PRG_BANK_SIZE, self.prg_swap) {
+            (0, false) | (2, true) => self.prg_banks[0] as usize,
+            (1, _) => self.prg_banks[1] as usize,
+            (0, true) | (2, false) => bank_count - 2,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = (self.chr_banks[(address as usize & 0x1FFF) / CHR_BANK_SIZE] >> self.chr_shift) as usize;
+        (bank * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+
+    fn write_chr_bank(&mut self, register: u16, value: u8) {
+        let index = (((register >> 12) - 0xB) * 2 + ((register >> 1) & 1)) as usize;
+        let bank = self.chr_banks[index];
+        self.chr_banks[index] = if register & 1 == 0 {
+            (bank & 0x1F0) | (value & 0x0F) as u16
+        } else {
+            let high_mask = if self.vrc2 { 0x0F } else { 0x1F };
+            (bank & 0x0F) | (((value & high_mask) as u16) << 4)
+        };
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled && !self.prg_ram.is_empty() => {
+                self.prg_ram[(address as usize - 0x6000) % self.prg_ram.len()]
+            }
+            0x6000..=0x6FFF if self.vrc2 => self.latch,
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            if self.prg_ram_enabled && !self.prg_ram.is_empty() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(address as usize - 0x6000) % len] = value;
+            } else if self.vrc2 && address < 0x7000 {
+                self.latch = value & 1;
+            }
+            return;
+        }
+
+        let register = self.register(address);
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = value & 0x1F,
+            0x9000..=0x9003 if self.vrc2 => {
+                self.mirroring = if value & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            0x9000 => {
+                self.mirroring = match value & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x9002 => {
+                self.prg_ram_enabled = value & 0b01 != 0;
+                self.prg_swap = value & 0b10 != 0;
+            }
+            0xA000..=0xA003 => self.prg_banks[1] = value & 0x1F,
+            0xB000..=0xE003 => self.write_chr_bank(register, value),
+            0xF000 if !self.vrc2 => self.irq.write_latch_low(value),
+            0xF001 if !self.vrc2 => self.irq.write_latch_high(value),
+            0xF002 if !self.vrc2 => self.irq.write_control(value),
+            0xF003 if !self.vrc2 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = value;
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    fn cartridge(mapper: u16, submapper: u8) -> Cartridge {
+        Cartridge {
+            prg_rom: (0..16).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: (0..256).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect(),
+            mapper,
+            submapper,
+            mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        }
+    }
+
+    #[test]
+    fn test_vrc4e_address_lines() {
+        let mut mapper = Vrc4::new(&cartridge(23, 2));
+        // $B004 is CHR bank 0 high nibble on VRC4e (A2)
+        mapper.cpu_write(0xB000, 0x05);
+        mapper.cpu_write(0xB004, 0x01);
+        assert_eq!(mapper.chr_read(0x0000), 0x15);
+        mapper.cpu_write(0x8000, 3);
+        mapper.cpu_write(0x9008, 0b10);
+        assert_eq!(mapper.cpu_read(0xC000), 3);
+        assert_eq!(mapper.cpu_read(0x8000), 14);
+    }
+
+    #[test]
+    fn test_vrc2a_chr_shift() {
+        let mut mapper = Vrc4::new(&cartridge(22, 0));
+        mapper.cpu_write(0xB000, 0x07);
+        assert_eq!(mapper.chr_read(0x0000), 3);
+        mapper.cpu_write(0x9000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_vrc4_cycle_irq() {
+        let mut mapper = Vrc4::new(&cartridge(25, 1));
+        mapper.cpu_write(0xF000, 0x0E);
+        mapper.cpu_write(0xF002, 0x0F);
+        mapper.cpu_write(0xF001, 0b110);
+        mapper.cpu_clock();
+        assert!(!mapper.irq_pending());
+        mapper.cpu_clock();
+        assert!(mapper.irq_pending());
+        mapper.cpu_write(0xF003, 0);
+        assert!(!mapper.irq_pending());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Mappers 24 (VRC6a) and 26 (VRC6b, A0/A1 swapped) https://www.nesdev.org/wiki/VRC6
+///
+/// The expansion audio registers ($9000-$B002) are accepted and ignored.
+pub struct Vrc6 {
+    swap_select_lines: bool,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    prg_bank_16k: u8,
+    prg_bank_8k: u8,
+    chr_banks: [u8; 8],
+    banking_mode: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc6 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let (chr, chr_is_ram) = super::chr_memory(cartridge);
+        Vrc6 {
+            swap_select_lines: cartridge.mapper == 26,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; std::cmp::max(super::prg_ram_size(cartridge), 0x2000)],
+            chr,
+            chr_is_ram,
+            prg_bank_16k: 0,
+            prg_bank_8k: 0,
+            chr_banks: [0; 8],
+            banking_mode: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    fn register(&self, address: u16) -> u16 {
+        if self.swap_select_lines {
+            (address & 0xF000) | ((address & 1) << 1) | ((address & 2) >> 1)
+        } else {
+            address & 0xF003
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.banking_mode & 0b1000_0000 != 0
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match (address as usize - 0x8000) / PRG_BANK_SIZE {
+            slot @ (0 | 1) => self.prg_bank_16k as usize * 2 + slot,
+            2 => self.prg_bank_8k as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let address = address as usize & 0x1FFF;
+        let slot = address / CHR_BANK_SIZE;
+        let a10 = slot & 1;
+        // 2KB banks either take A10 from the PPU or from the register itself
+        let two_kb = |register: u8| {
+            if self.banking_mode & 0b10_0000 != 0 {
+                (register as usize & !1) | a10
+            } else {
+                register as usize
+            }
+        };
+        let bank = match (self.banking_mode & 0b11, slot) {
+            (0, slot) => self.chr_banks[slot] as usize,
+            (1, slot) => two_kb(self.chr_banks[slot / 2]),
+            (_, 0..=3) => self.chr_banks[slot] as usize,
+            (_, slot) => two_kb(self.chr_banks[4 + (slot - 4) / 2]),
+        };
+        (bank * CHR_BANK_SIZE + (address & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            if self.prg_ram_enabled() {
+                self.prg_ram[address as usize - 0x6000] = value;
+            }
+            return;
+        }
+
+        match self.register(address) {
+            0x8000..=0x8003 => self.prg_bank_16k = value & 0x0F,
+            0xB003 => self.banking_mode = value,
+            0xC000..=0xC003 => self.prg_bank_8k = value & 0x1F,
+            register @ (0xD000..=0xD003 | 0xE000..=0xE003) => {
+                let index = ((register >> 12) - 0xD) * 4 + (register & 0b11);
+                self.chr_banks[index as usize] = value;
+            }
+            0xF000 => self.irq.write_latch(value),
+            0xF001 => self.irq.write_control(value),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = value;
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking_mode >> 2) & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    fn cartridge(mapper: u16) -> Cartridge {
+        Cartridge {
+            prg_rom: (0..32).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: (0..256).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect(),
+            mapper,
+            submapper: 0,
+            mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        }
+    }
+
+    #[test]
+    fn test_prg_layout() {
+        let mut mapper = Vrc6::new(&cartridge(24));
+        mapper.cpu_write(0x8000, 2);
+        mapper.cpu_write(0xC000, 9);
+        assert_eq!(mapper.cpu_read(0x8000), 4);
+        assert_eq!(mapper.cpu_read(0xA000), 5);
+        assert_eq!(mapper.cpu_read(0xC000), 9);
+        assert_eq!(mapper.cpu_read(0xE000), 31);
+    }
+
+    #[test]
+    fn test_vrc6b_swapped_lines() {
+        let mut mapper = Vrc6::new(&cartridge(26));
+        mapper.cpu_write(0xD002, 0x33);
+        assert_eq!(mapper.chr_read(0x0400), 0x33);
+        mapper.cpu_write(0xB003, 0b1000_0100);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.cpu_write(0x6000, 0x77);
+        assert_eq!(mapper.cpu_read(0x6000), 0x77);
+    }
+}
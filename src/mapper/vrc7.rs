@@ -0,0 +1,191 @@
+use crate::cartridge::{Cartridge, Mirroring};
+
+use super::vrc_irq::VrcIrq;
+use super::Mapper;
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x400;
+
+/// Mapper 85: Konami VRC7 https://www.nesdev.org/wiki/VRC7
+///
+/// VRC7a selects the odd registers with A4, VRC7b with A3. The FM synthesizer at $9010/$9030 is not emulated.
+pub struct Vrc7 {
+    select_line: u16,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    control: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc7 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let (chr, chr_is_ram) = super::chr_memory(cartridge);
+        Vrc7 {
+            select_line: match cartridge.submapper {
+                1 => 0x08,
+                2 => 0x10,
+                _ => 0x18,
+            },
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; std::cmp::max(super::prg_ram_size(cartridge), 0x2000)],
+            chr,
+            chr_is_ram,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        self.control & 0b1000_0000 != 0
+    }
+
+    fn prg_rom_offset(&self, address: u16) -> usize {
+        let bank_count = self.prg_rom.len() / PRG_BANK_SIZE;
+        let bank = match (address as usize - 0x8000) / PRG_BANK_SIZE {
+            slot @ 0..=2 => self.prg_banks[slot] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * PRG_BANK_SIZE + (address as usize & (PRG_BANK_SIZE - 1))
+    }
+
+    fn chr_offset(&self, address: u16) -> usize {
+        let bank = self.chr_banks[(address as usize & 0x1FFF) / CHR_BANK_SIZE] as usize;
+        (bank * CHR_BANK_SIZE + (address as usize & (CHR_BANK_SIZE - 1))) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_read(&mut self, address: u16) -> u8 {
+        match address {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[address as usize - 0x6000],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_rom_offset(address)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, address: u16, value: u8) {
+        if let 0x6000..=0x7FFF = address {
+            if self.prg_ram_enabled() {
+                self.prg_ram[address as usize - 0x6000] = value;
+            }
+            return;
+        }
+
+        // the FM synthesizer ports, which VRC7b's A3 decoding would otherwise take for the $9000 PRG bank
+        if let 0x9010 | 0x9030 = address {
+            return;
+        }
+
+        let odd = address & self.select_line != 0;
+        match (address & 0xF000, odd) {
+            (0x8000, false) => self.prg_banks[0] = value & 0x3F,
+            (0x8000, true) => self.prg_banks[1] = value & 0x3F,
+            (0x9000, false) => self.prg_banks[2] = value & 0x3F,
+            (register @ 0xA000..=0xD000, odd) => {
+                let index = ((register >> 12) - 0xA) * 2 + if odd { 1 } else { 0 };
+                self.chr_banks[index as usize] = value;
+            }
+            (0xE000, false) => self.control = value,
+            (0xE000, true) => self.irq.write_latch(value),
+            (0xF000, false) => self.irq.write_control(value),
+            (0xF000, true) => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, address: u16) -> u8 {
+        self.chr[self.chr_offset(address)]
+    }
+
+    fn chr_write(&mut self, address: u16, value: u8) {
+        if self.chr_is_ram {
+            let offset = self.chr_offset(address);
+            self.chr[offset] = value;
+        }
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq_pending(&self) -> bool {
+        self.irq.pending()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Region;
+
+    #[test]
+    fn test_vrc7a_registers() {
+        let mut mapper = Vrc7::new(&Cartridge {
+            prg_rom: (0..16).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: (0..128).flat_map(|bank| vec![bank as u8; CHR_BANK_SIZE]).collect(),
+            mapper: 85,
+            submapper: 2,
+            mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        });
+        mapper.cpu_write(0x8010, 7);
+        mapper.cpu_write(0xD010, 99);
+        mapper.cpu_write(0xE000, 0b1000_0001);
+        assert_eq!(mapper.cpu_read(0xA000), 7);
+        assert_eq!(mapper.cpu_read(0xE000), 15);
+        assert_eq!(mapper.chr_read(0x1C00), 99);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_vrc7b_audio_ports_leave_prg_banks() {
+        let mut mapper = Vrc7::new(&Cartridge {
+            prg_rom: (0..16).flat_map(|bank| vec![bank as u8; PRG_BANK_SIZE]).collect(),
+            chr_rom: vec![0; 8 * CHR_BANK_SIZE],
+            mapper: 85,
+            submapper: 1,
+            mirroring: Mirroring::Vertical,
+            battery: false,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            region: Region::Ntsc,
+            title: None,
+        });
+        mapper.cpu_write(0x9000, 3);
+        mapper.cpu_write(0x9010, 0x20);
+        mapper.cpu_write(0x9030, 0x40);
+        assert_eq!(mapper.cpu_read(0xC000), 3);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::cpu::Cpu;
+use crate::input_script::InputScript;
+use crate::palette::Palette;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// The console without a frontend: runs whole frames and hands out the picture, so it works on
+/// machines without a display.
+pub struct Nes {
+    cpu: Cpu,
+    palette: Palette,
+}
+
+impl Nes {
+    pub fn new(cartridge: &Cartridge) -> Result<Self, String> {
+        let mut bus = Bus::new();
+        bus.load_cartridge(cartridge)?;
+        Ok(Nes::power_on(bus))
+    }
+
+    /// Famicom Disk System image, `bios` being the 8KB disk system BIOS ROM
+    pub fn with_fds(cartridge: &Cartridge, bios: &[u8]) -> Result<Self, String> {
+        let mut bus = Bus::new();
+        bus.load_fds(cartridge, bios)?;
+        Ok(Nes::power_on(bus))
+    }
+
+    fn power_on(bus: Bus) -> Self {
+        let mut cpu = Cpu::new(bus);
+        cpu.reset();
+        Nes {
+            cpu,
+            palette: Palette::default(),
+        }
+    }
+
+    /// Presses the reset button: RAM and the cartridge keep their state
+    pub fn reset(&mut self) {
+        self.cpu.bus.reset();
+        self.cpu.reset();
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    pub fn cpu(&self) -> &Cpu {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    pub fn bus(&self) -> &Bus {
+        &self.cpu.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.cpu.bus
+    }
+
+    /// Holds the buttons `script` gives for the next frame on both controllers
+    pub fn apply_input(&mut self, script: &InputScript) {
+        let buttons = script.buttons(self.frame());
+        for (port, buttons) in buttons.into_iter().enumerate() {
+            self.cpu.bus.joypad_mut(port).set_buttons(buttons);
+        }
+    }
+
+    /// Frames completed since power on
+    pub fn frame(&self) -> u64 {
+        self.cpu.bus.ppu().frame()
+    }
+
+    /// Runs until the PPU finishes the current frame
+    pub fn run_frame(&mut self) -> Result<(), String> {
+        let frame = self.frame();
+        while self.frame() == frame {
+            self.cpu.step()?;
+        }
+        Ok(())
+    }
+
+    pub fn run_frames(&mut self, frames: u64) -> Result<(), String> {
+        for _ in 0..frames {
+            self.run_frame()?;
+        }
+        Ok(())
+    }
+
+    /// The last complete frame as packed RGB24, `SCREEN_WIDTH` x `SCREEN_HEIGHT` pixels
+    pub fn rgb_frame(&self) -> Vec<u8> {
+        let mut rgb = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT * 3];
+        self.palette.frame_to_rgb(self.cpu.bus.ppu().frame_buffer(), &mut rgb);
+        rgb
+    }
+
+    /// Turns on sound, mono at `sample_rate` Hz
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.cpu.bus.apu_mut().set_sample_rate(sample_rate);
+    }
+
+    /// Appends the samples of the frames run since the last call to `out`
+    pub fn take_samples(&mut self, out: &mut Vec<i16>) {
+        self.cpu.bus.apu_mut().take_samples(out);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test::ines_image;
+    use crate::cartridge::Region;
+
+    /// NROM program that sets the backdrop colour to $2A, turns on the background and spins
+    fn backdrop_rom() -> Cartridge {
+        let mut raw = ines_image(0, 1, 1, 0);
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x3F, 0x8D, 0x06, 0x20, // LDA #$3F, STA $2006
+            0xA9, 0x00, 0x8D, 0x06, 0x20, // LDA #$00, STA $2006
+            0xA9, 0x2A, 0x8D, 0x07, 0x20, // LDA #$2A, STA $2007
+            0xA9, 0x08, 0x8D, 0x01, 0x20, // LDA #$08, STA $2001
+            0x4C, 0x14, 0xC0,             // JMP $C014
+        ];
+        raw[16..16 + program.len()].copy_from_slice(&program);
+        // reset vector at $FFFC -> $C000
+        raw[16 + 0x3FFC] = 0x00;
+        raw[16 + 0x3FFD] = 0xC0;
+        Cartridge::from_ines(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_runs_frames_headless() {
+        let mut nes = Nes::new(&backdrop_rom()).unwrap();
+        nes.run_frames(2).unwrap();
+        assert_eq!(nes.frame(), 2);
+        let rgb = nes.rgb_frame();
+        assert_eq!(rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        let expected = Palette::default().rgb(0x2A);
+        assert_eq!(&rgb[..3], &expected);
+        assert_eq!(&rgb[rgb.len() - 3..], &expected);
+    }
+
+    #[test]
+    fn test_audio_samples_per_frame() {
+        let mut nes = Nes::new(&backdrop_rom()).unwrap();
+        let mut samples = Vec::new();
+        nes.run_frames(10).unwrap();
+        nes.take_samples(&mut samples);
+        assert!(samples.is_empty());
+
+        nes.set_sample_rate(48_000);
+        nes.run_frames(60).unwrap();
+        nes.take_samples(&mut samples);
+        let expected = 60.0 * 48_000.0 / Region::Ntsc.frame_rate();
+        assert!((samples.len() as f64 - expected).abs() < 2.0);
+        // every channel is off, the resting triangle level only makes a step the high-pass filters take out
+        assert!(samples[samples.len() - 100..].iter().all(|sample| sample.abs() < 10));
+    }
+}
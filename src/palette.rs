@@ -0,0 +1,127 @@
+use std::fs;
+use std::path::Path;
+
+/// Number of colours the PPU can output, six bits of palette index
+pub const COLOURS: usize = 64;
+/// Colours for every combination of the three PPUMASK emphasis bits
+pub const EMPHASIS_COLOURS: usize = 8 * COLOURS;
+
+/// How much an emphasis bit darkens the other two channels, as measured on a 2C02
+const EMPHASIS_ATTENUATION: f32 = 0.816328;
+
+/// A 2C02 palette https://www.nesdev.org/wiki/PPU_palettes
+#[rustfmt::skip]
+const DEFAULT_PALETTE: [[u8; 3]; COLOURS] = [
+    [0x80, 0x80, 0x80], [0x00, 0x3D, 0xA6], [0x00, 0x12, 0xB0], [0x44, 0x00, 0x96],
+    [0xA1, 0x00, 0x5E], [0xC7, 0x00, 0x28], [0xBA, 0x06, 0x00], [0x8C, 0x17, 0x00],
+    [0x5C, 0x2F, 0x00], [0x10, 0x45, 0x00], [0x05, 0x4A, 0x00], [0x00, 0x47, 0x2E],
+    [0x00, 0x41, 0x66], [0x00, 0x00, 0x00], [0x05, 0x05, 0x05], [0x05, 0x05, 0x05],
+    [0xC7, 0xC7, 0xC7], [0x00, 0x77, 0xFF], [0x21, 0x55, 0xFF], [0x82, 0x37, 0xFA],
+    [0xEB, 0x2F, 0xB5], [0xFF, 0x29, 0x50], [0xFF, 0x22, 0x00], [0xD6, 0x32, 0x00],
+    [0xC4, 0x62, 0x00], [0x35, 0x80, 0x00], [0x05, 0x8F, 0x00], [0x00, 0x8A, 0x55],
+    [0x00, 0x99, 0xCC], [0x21, 0x21, 0x21], [0x09, 0x09, 0x09], [0x09, 0x09, 0x09],
+    [0xFF, 0xFF, 0xFF], [0x0F, 0xD7, 0xFF], [0x69, 0xA2, 0xFF], [0xD4, 0x80, 0xFF],
+    [0xFF, 0x45, 0xF3], [0xFF, 0x61, 0x8B], [0xFF, 0x88, 0x33], [0xFF, 0x9C, 0x12],
+    [0xFA, 0xBC, 0x20], [0x9F, 0xE3, 0x0E], [0x2B, 0xF0, 0x35], [0x0C, 0xF0, 0xA4],
+    [0x05, 0xFB, 0xFF], [0x5E, 0x5E, 0x5E], [0x0D, 0x0D, 0x0D], [0x0D, 0x0D, 0x0D],
+    [0xFF, 0xFF, 0xFF], [0xA6, 0xFC, 0xFF], [0xB3, 0xEC, 0xFF], [0xDA, 0xAB, 0xEB],
+    [0xFF, 0xA8, 0xF9], [0xFF, 0xAB, 0xB3], [0xFF, 0xD2, 0xB0], [0xFF, 0xEF, 0xA6],
+    [0xFF, 0xF7, 0x9C], [0xD7, 0xE8, 0x95], [0xA6, 0xED, 0xAF], [0xA2, 0xF2, 0xDA],
+    [0x99, 0xFF, 0xFC], [0xDD, 0xDD, 0xDD], [0x11, 0x11, 0x11], [0x11, 0x11, 0x11],
+];
+
+/// Turns the PPU's output into RGB. Pixels are the 6 bit palette index with the PPUMASK emphasis bits
+/// (red, green, blue) in bits 6-8, as found in `Ppu::frame_buffer`.
+pub struct Palette {
+    colours: Vec<[u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::with_emphasis(&DEFAULT_PALETTE)
+    }
+}
+
+impl Palette {
+    /// Reads a `.pal` file: 64 RGB triplets, or 512 when the emphasis combinations are included
+    pub fn from_pal(data: &[u8]) -> Result<Self, String> {
+        let colours: Vec<[u8; 3]> = data.chunks_exact(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+        match (data.len() % 3, colours.len()) {
+            (0, COLOURS) => Ok(Palette::with_emphasis(&colours)),
+            (0, EMPHASIS_COLOURS) => Ok(Palette { colours }),
+            _ => Err(format!(
+                "Palette files should be {} or {} bytes, got {}",
+                COLOURS * 3,
+                EMPHASIS_COLOURS * 3,
+                data.len()
+            )),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let data = fs::read(path).map_err(|e| format!("Can't read palette {}: {}", path.display(), e))?;
+        Palette::from_pal(&data)
+    }
+
+    /// Derives the emphasised colours by darkening the channels that aren't emphasised. The blacks in
+    /// columns $E and $F stay as they are.
+    fn with_emphasis(base: &[[u8; 3]]) -> Self {
+        let colours = (0..EMPHASIS_COLOURS)
+            .map(|pixel| {
+                let (emphasis, colour) = (pixel / COLOURS, base[pixel % COLOURS]);
+                if pixel & 0x0E == 0x0E {
+                    return colour;
+                }
+                let mut rgb = colour;
+                for (channel, value) in rgb.iter_mut().enumerate() {
+                    if emphasis & !(1 << channel) != 0 {
+                        *value = (*value as f32 * EMPHASIS_ATTENUATION).round() as u8;
+                    }
+                }
+                rgb
+            })
+            .collect();
+        Palette { colours }
+    }
+
+    pub fn rgb(&self, pixel: u16) -> [u8; 3] {
+        self.colours[pixel as usize % EMPHASIS_COLOURS]
+    }
+
+    /// Converts a whole frame to packed RGB24, 3 bytes per pixel
+    pub fn frame_to_rgb(&self, frame: &[u16], rgb: &mut [u8]) {
+        for (pixel, out) in frame.iter().zip(rgb.chunks_exact_mut(3)) {
+            out.copy_from_slice(&self.rgb(*pixel));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_default_palette() {
+        let palette = Palette::default();
+        assert_eq!(palette.rgb(0x30), [0xFF, 0xFF, 0xFF]);
+        assert_eq!(palette.rgb(0x0F), [0x05, 0x05, 0x05]);
+        // red emphasis darkens green and blue, blacks are left alone
+        assert_eq!(palette.rgb(0x40 | 0x30), [0xFF, 0xD0, 0xD0]);
+        assert_eq!(palette.rgb(0x1C0 | 0x0F), [0x05, 0x05, 0x05]);
+        assert_eq!(palette.rgb(0x1C0 | 0x30), [0xD0, 0xD0, 0xD0]);
+    }
+
+    #[test]
+    fn test_pal_files() {
+        let small: Vec<u8> = (0..COLOURS * 3).map(|i| i as u8).collect();
+        let palette = Palette::from_pal(&small).unwrap();
+        assert_eq!(palette.rgb(1), [3, 4, 5]);
+        assert_eq!(palette.rgb(0x100 | 1), [2, 3, 5]);
+
+        let full: Vec<u8> = (0..EMPHASIS_COLOURS * 3).map(|i| (i / 3) as u8).collect();
+        let palette = Palette::from_pal(&full).unwrap();
+        assert_eq!(palette.rgb(0x101), [1, 1, 1]);
+
+        assert!(Palette::from_pal(&[0; 100]).is_err());
+    }
+}
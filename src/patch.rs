@@ -0,0 +1,294 @@
+const IPS_TAG: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const BPS_TAG: &[u8] = b"BPS1";
+const UPS_TAG: &[u8] = b"UPS1";
+/// BPS and UPS both end with the source, target and patch CRC32
+const FOOTER_SIZE: usize = 12;
+
+/// Applies an IPS, BPS or UPS patch (detected by its header) to a ROM image and returns the patched copy
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(IPS_TAG) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(BPS_TAG) {
+        apply_bps(rom, patch)
+    } else if patch.starts_with(UPS_TAG) {
+        apply_ups(rom, patch)
+    } else {
+        Err("Unknown patch format".to_string())
+    }
+}
+
+struct PatchReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], position: usize) -> Self {
+        PatchReader { data, position }
+    }
+
+    fn read(&mut self) -> Result<u8, String> {
+        let value = *self.data.get(self.position).ok_or("Patch is truncated")?;
+        self.position += 1;
+        Ok(value)
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let bytes = self.data.get(self.position..self.position + len).ok_or("Patch is truncated")?;
+        self.position += len;
+        Ok(bytes)
+    }
+
+    fn read_be(&mut self, len: usize) -> Result<usize, String> {
+        let mut value = 0;
+        for _ in 0..len {
+            value = value << 8 | self.read()? as usize;
+        }
+        Ok(value)
+    }
+
+    /// Variable length number used by BPS and UPS, each byte adds 7 bits with an implicit +1 offset
+    fn read_number(&mut self) -> Result<usize, String> {
+        let mut data: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let x = self.read()? as usize;
+            data = data.checked_add((x & 0x7F) * shift).ok_or("Patch number overflow")?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_shl(7).ok_or("Patch number overflow")?;
+            data = data.checked_add(shift).ok_or("Patch number overflow")?;
+        }
+    }
+}
+
+fn read_footer_crc(patch: &[u8], index: usize) -> u32 {
+    let start = patch.len() - FOOTER_SIZE + index * 4;
+    u32::from_le_bytes([patch[start], patch[start + 1], patch[start + 2], patch[start + 3]])
+}
+
+fn check_crc(name: &str, data: &[u8], expected: u32) -> Result<(), String> {
+    let actual = crc32fast::hash(data);
+    if actual != expected {
+        return Err(format!("{} checksum mismatch: expected {:08X}, got {:08X}", name, expected, actual));
+    }
+    Ok(())
+}
+
+/// https://zerosoft.zophar.net/ips.php, including RLE records and the truncation extension
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let mut target = rom.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_TAG.len());
+    loop {
+        if reader.read_bytes(3)? == IPS_EOF {
+            break;
+        }
+        reader.position -= 3;
+        let offset = reader.read_be(3)?;
+        let size = reader.read_be(2)?;
+        let (len, rle_value) = if size == 0 {
+            (reader.read_be(2)?, Some(reader.read()?))
+        } else {
+            (size, None)
+        };
+        if target.len() < offset + len {
+            target.resize(offset + len, 0);
+        }
+        match rle_value {
+            Some(value) => target[offset..offset + len].iter_mut().for_each(|byte| *byte = value),
+            None => target[offset..offset + len].copy_from_slice(reader.read_bytes(len)?),
+        }
+    }
+    if reader.position + 3 <= patch.len() {
+        let truncated_size = reader.read_be(3)?;
+        target.truncate(truncated_size);
+    }
+    Ok(target)
+}
+
+/// https://github.com/blakesmith/rombp/blob/master/docs/bps_spec.md
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < BPS_TAG.len() + FOOTER_SIZE {
+        return Err("Patch is truncated".to_string());
+    }
+    check_crc("BPS patch", &patch[..patch.len() - 4], read_footer_crc(patch, 2))?;
+    check_crc("BPS source", rom, read_footer_crc(patch, 0))?;
+
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], BPS_TAG.len());
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    let metadata_size = reader.read_number()?;
+    reader.read_bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(format!("BPS source size mismatch: expected {}, got {}", source_size, rom.len()));
+    }
+
+    let mut target = vec![0; target_size];
+    let mut output = 0;
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+    let out_of_range = || "BPS patch points outside the ROM".to_string();
+    while reader.position < reader.data.len() {
+        let data = reader.read_number()?;
+        let len = (data >> 2) + 1;
+        if output + len > target_size {
+            return Err(out_of_range());
+        }
+        match data & 0b11 {
+            // SourceRead
+            0 => target[output..output + len].copy_from_slice(rom.get(output..output + len).ok_or_else(out_of_range)?),
+            // TargetRead
+            1 => target[output..output + len].copy_from_slice(reader.read_bytes(len)?),
+            // SourceCopy
+            2 => {
+                source_offset += relative_offset(reader.read_number()?);
+                let start = usize::try_from(source_offset).map_err(|_| out_of_range())?;
+                target[output..output + len].copy_from_slice(rom.get(start..start + len).ok_or_else(out_of_range)?);
+                source_offset += len as isize;
+            }
+            // TargetCopy, byte by byte since source and destination may overlap
+            _ => {
+                target_offset += relative_offset(reader.read_number()?);
+                for i in 0..len {
+                    let from = usize::try_from(target_offset).map_err(|_| out_of_range())? + i;
+                    if from >= output + i {
+                        return Err(out_of_range());
+                    }
+                    target[output + i] = target[from];
+                }
+                target_offset += len as isize;
+            }
+        }
+        output += len;
+    }
+
+    check_crc("BPS target", &target, read_footer_crc(patch, 1))?;
+    Ok(target)
+}
+
+fn relative_offset(data: usize) -> isize {
+    let magnitude = (data >> 1) as isize;
+    if data & 1 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+/// UPS: XOR runs against the source, https://www.romhacking.net/documents/392/
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.len() < UPS_TAG.len() + FOOTER_SIZE {
+        return Err("Patch is truncated".to_string());
+    }
+    check_crc("UPS patch", &patch[..patch.len() - 4], read_footer_crc(patch, 2))?;
+    check_crc("UPS source", rom, read_footer_crc(patch, 0))?;
+
+    let mut reader = PatchReader::new(&patch[..patch.len() - FOOTER_SIZE], UPS_TAG.len());
+    let source_size = reader.read_number()?;
+    let target_size = reader.read_number()?;
+    if source_size != rom.len() {
+        return Err(format!("UPS source size mismatch: expected {}, got {}", source_size, rom.len()));
+    }
+
+    let mut target = rom.to_vec();
+    target.resize(target_size, 0);
+    let mut offset = 0;
+    while reader.position < reader.data.len() {
+        offset += reader.read_number()?;
+        loop {
+            let x = reader.read()?;
+            if let Some(byte) = target.get_mut(offset) {
+                *byte ^= x;
+            }
+            offset += 1;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+
+    check_crc("UPS target", &target, read_footer_crc(patch, 1))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(mut data: usize) -> Vec<u8> {
+        let mut bytes = vec![];
+        loop {
+            let x = (data & 0x7F) as u8;
+            data >>= 7;
+            if data == 0 {
+                bytes.push(0x80 | x);
+                return bytes;
+            }
+            bytes.push(x);
+            data -= 1;
+        }
+    }
+
+    fn with_footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend(crc32fast::hash(source).to_le_bytes());
+        patch.extend(crc32fast::hash(target).to_le_bytes());
+        let crc = crc32fast::hash(&patch);
+        patch.extend(crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_ips_records_rle_and_truncation() {
+        let rom = vec![0u8; 16];
+        let mut patch = b"PATCH".to_vec();
+        patch.extend([0x00, 0x00, 0x02, 0x00, 0x02, 0xAA, 0xBB]);
+        patch.extend([0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend(b"EOF");
+        patch.extend([0x00, 0x00, 0x0A]);
+        let patched = apply(&rom, &patch).unwrap();
+        assert_eq!(patched, vec![0, 0, 0xAA, 0xBB, 0, 0, 0, 0, 0xCC, 0xCC]);
+        assert_eq!(rom, vec![0u8; 16]);
+    }
+
+    #[test]
+    fn test_bps() {
+        let source = b"ABCDEFGH".to_vec();
+        let target = b"ABCDxyxyxyEF".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(0));
+        // SourceRead 4, TargetRead "xy", TargetCopy 4 from target offset 4, SourceCopy 2 from source offset 4
+        patch.extend(number((4 - 1) << 2));
+        patch.extend(number((2 - 1) << 2 | 1));
+        patch.extend(b"xy");
+        patch.extend(number((4 - 1) << 2 | 3));
+        patch.extend(number(4 << 1));
+        patch.extend(number((2 - 1) << 2 | 2));
+        patch.extend(number(4 << 1));
+        let patch = with_footer(patch, &source, &target);
+        assert_eq!(apply(&source, &patch).unwrap(), target);
+
+        let mut corrupted = patch.clone();
+        corrupted[6] ^= 1;
+        assert!(apply(&source, &corrupted).unwrap_err().contains("checksum mismatch"));
+        assert!(apply(b"ABCDEFGX", &patch).unwrap_err().contains("BPS source checksum mismatch"));
+    }
+
+    #[test]
+    fn test_ups() {
+        let source = b"ABCDEFGH".to_vec();
+        let target = b"ABcDEFGH!".to_vec();
+        let mut patch = b"UPS1".to_vec();
+        patch.extend(number(source.len()));
+        patch.extend(number(target.len()));
+        patch.extend(number(2));
+        patch.extend([b'C' ^ b'c', 0]);
+        patch.extend(number(4));
+        patch.extend([b'!', 0]);
+        let patch = with_footer(patch, &source, &target);
+        assert_eq!(apply(&source, &patch).unwrap(), target);
+    }
+}
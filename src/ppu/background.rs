@@ -0,0 +1,133 @@
+use super::Ppu;
+
+/// Background fetch pipeline https://www.nesdev.org/wiki/PPU_rendering
+///
+/// Each 8 dots fetch a nametable byte, an attribute byte and the two pattern bitplanes for the tile after
+/// next, which get loaded into the low bytes of the shift registers. Pixels come out of bit 15 - fine X.
+#[derive(Default)]
+pub(super) struct Background {
+    next_tile: u8,
+    next_attribute: u8,
+    next_pattern_low: u8,
+    next_pattern_high: u8,
+
+    pattern_low: u16,
+    pattern_high: u16,
+    attribute_low: u16,
+    attribute_high: u16,
+}
+
+impl Ppu {
+    /// Fetches and scroll updates for the current dot, only runs on the visible and pre-render lines
+    /// while rendering is enabled
+    pub(super) fn clock_background(&mut self) {
+        let dot = self.dot;
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.shift_background();
+        }
+        let reload = (9..=257).contains(&dot) || (329..=337).contains(&dot);
+        if reload && (dot - 1).is_multiple_of(8) {
+            self.load_background_shifters();
+        }
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match (dot - 1) % 8 {
+                0 => self.background.next_tile = self.read_vram(0x2000 | (self.v & 0x0FFF)),
+                2 => {
+                    let v = self.v;
+                    let attribute = self.read_vram(0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07));
+                    let shift = ((v >> 4) & 0b100) | (v & 0b10);
+                    self.background.next_attribute = (attribute >> shift) & 0b11;
+                }
+                4 => {
+                    let address = self.background_pattern_address();
+                    self.background.next_pattern_low = self.read_vram(address);
+                }
+                6 => {
+                    let address = self.background_pattern_address() + 8;
+                    self.background.next_pattern_high = self.read_vram(address);
+                }
+                7 => self.increment_coarse_x(),
+                _ => {}
+            }
+        }
+        match dot {
+            256 => self.increment_y(),
+            257 => self.v = (self.v & !0x041F) | (self.t & 0x041F),
+            // unused nametable fetches, which some mappers (MMC5) count
+            337 | 339 => {
+                self.read_vram(0x2000 | (self.v & 0x0FFF));
+            }
+            280..=304 if self.scanline == self.pre_render_scanline() => {
+                self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+            }
+            _ => {}
+        }
+    }
+
+    /// Pixel value (0-3) and palette (0-3) of the background at screen column `x`
+    pub(super) fn background_pixel(&self, x: usize) -> (u8, u8) {
+        let clipped = x < 8 && !self.mask.contains(super::MaskRegister::LEFTMOST_8PXL_BACKGROUND);
+        if !self.mask.show_background() || clipped {
+            return (0, 0);
+        }
+        let bit = 0x8000 >> self.fine_x;
+        let bg = &self.background;
+        let plane = |register: u16| (register & bit != 0) as u8;
+        (
+            plane(bg.pattern_high) << 1 | plane(bg.pattern_low),
+            plane(bg.attribute_high) << 1 | plane(bg.attribute_low),
+        )
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        let fine_y = (self.v >> 12) & 0b111;
+        self.ctrl.background_pattern_addr() + self.background.next_tile as u16 * 16 + fine_y
+    }
+
+    fn shift_background(&mut self) {
+        let bg = &mut self.background;
+        bg.pattern_low <<= 1;
+        bg.pattern_high <<= 1;
+        bg.attribute_low <<= 1;
+        bg.attribute_high <<= 1;
+    }
+
+    fn load_background_shifters(&mut self) {
+        let bg = &mut self.background;
+        let fill = |bit: u8| if bit != 0 { 0xFF } else { 0x00 };
+        bg.pattern_low = (bg.pattern_low & 0xFF00) | bg.next_pattern_low as u16;
+        bg.pattern_high = (bg.pattern_high & 0xFF00) | bg.next_pattern_high as u16;
+        bg.attribute_low = (bg.attribute_low & 0xFF00) | fill(bg.next_attribute & 0b01);
+        bg.attribute_high = (bg.attribute_high & 0xFF00) | fill(bg.next_attribute & 0b10);
+    }
+
+    /// https://www.nesdev.org/wiki/PPU_scrolling#Coarse_X_increment
+    fn increment_coarse_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400;
+        } else {
+            self.v += 1;
+        }
+    }
+
+    /// https://www.nesdev.org/wiki/PPU_scrolling#Y_increment
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800;
+        } else if coarse_y == 31 {
+            // attribute rows wrap without switching nametable
+            coarse_y = 0;
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+}
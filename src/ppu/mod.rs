@@ -0,0 +1,857 @@
+mod background;
+pub mod registers;
+mod sprites;
+
+use crate::cartridge::Region;
+use crate::mapper::MapperRef;
+use background::Background;
+use registers::{ControlRegister, MaskRegister, StatusRegister};
+use sprites::Sprites;
+
+pub const SCREEN_WIDTH: usize = 256;
+pub const SCREEN_HEIGHT: usize = 240;
+
+const PATTERN_TABLES_END: u16 = 0x1FFF;
+const NAMETABLES: u16 = 0x2000;
+const NAMETABLES_MIRRORS_END: u16 = 0x3EFF;
+const PALETTE_RAM: u16 = 0x3F00;
+const NAMETABLE_SIZE: usize = 0x400;
+
+const DOTS_PER_SCANLINE: u16 = 341;
+const VISIBLE_SCANLINES: u16 = 240;
+/// Open bus bits fade to 0 roughly 600ms after they were last driven
+const IO_LATCH_DECAY_FRAMES: u64 = 36;
+
+/// Picture Processing Unit https://www.nesdev.org/wiki/PPU
+///
+/// The nametables live in the console's 2KB CIRAM (plus 2KB more for four screen boards), and the
+/// pattern tables in the cartridge, reached through the mapper.
+pub struct Ppu {
+    mapper: Option<MapperRef>,
+    region: Region,
+    vram: [u8; 4 * NAMETABLE_SIZE],
+    pub oam: [u8; 256],
+    palette: [u8; 32],
+
+    pub ctrl: ControlRegister,
+    pub mask: MaskRegister,
+    pub status: StatusRegister,
+    oam_addr: u8,
+
+    /// "Loopy" registers https://www.nesdev.org/wiki/PPU_scrolling:
+    /// current VRAM address, temporary VRAM address, fine X scroll and the $2005/$2006 write toggle
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    write_toggle: bool,
+
+    /// PPUDATA reads below the palette return the previous read
+    read_buffer: u8,
+    /// Last value driven on the CPU <-> PPU data bus, what write-only registers read back
+    /// https://www.nesdev.org/wiki/Open_bus_behavior#PPU_open_bus
+    io_latch: u8,
+    /// Frame each latch bit was last driven, for the decay
+    io_latch_refreshed: [u64; 8],
+
+    scanline: u16,
+    dot: u16,
+    frame: u64,
+    /// Set for the NMI edge, taken by the CPU through `poll_nmi`
+    nmi_pending: bool,
+    /// PPUSTATUS was read one dot before vblank, so this frame neither sets the flag nor fires NMI
+    suppress_vblank: bool,
+
+    background: Background,
+    sprites: Sprites,
+    /// Colour of every pixel, row by row: palette index (0-63) plus the emphasis bits in bits 6-8
+    frame_buffer: Vec<u16>,
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Ppu::new()
+    }
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            mapper: None,
+            region: Region::Ntsc,
+            vram: [0; 4 * NAMETABLE_SIZE],
+            oam: [0; 256],
+            palette: [0; 32],
+            ctrl: ControlRegister::empty(),
+            mask: MaskRegister::empty(),
+            status: StatusRegister::empty(),
+            oam_addr: 0,
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            write_toggle: false,
+            read_buffer: 0,
+            io_latch: 0,
+            io_latch_refreshed: [0; 8],
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            nmi_pending: false,
+            suppress_vblank: false,
+            background: Background::default(),
+            sprites: Sprites::default(),
+            frame_buffer: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+        }
+    }
+
+    /// Reset button: clears PPUCTRL, PPUMASK, the scroll latch and the read buffer. VRAM, OAM and the
+    /// palette keep their contents.
+    pub fn reset(&mut self) {
+        self.ctrl = ControlRegister::empty();
+        self.mask = MaskRegister::empty();
+        self.t = 0;
+        self.fine_x = 0;
+        self.write_toggle = false;
+        self.read_buffer = 0;
+        self.nmi_pending = false;
+    }
+
+    /// The last rendered picture, `SCREEN_WIDTH` x `SCREEN_HEIGHT` pixels for `Palette` to turn into RGB.
+    /// Complete once `frame()` moves on.
+    pub fn frame_buffer(&self) -> &[u16] {
+        &self.frame_buffer
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    /// Number of frames completed since power on
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    /// Advances one dot, the PPU runs 3 dots per CPU cycle https://www.nesdev.org/wiki/PPU_frame_timing
+    pub fn tick(&mut self) {
+        self.dot += 1;
+        // odd frames are one dot shorter when rendering, the pre-render line skips its last dot
+        let skip_dot = self.region == Region::Ntsc
+            && self.scanline == self.pre_render_scanline()
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.frame % 2 == 1
+            && self.mask.rendering_enabled();
+        if self.dot == DOTS_PER_SCANLINE || skip_dot {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > self.pre_render_scanline() {
+                self.scanline = 0;
+                self.frame += 1;
+            }
+        }
+
+        let rendering_line = self.scanline < VISIBLE_SCANLINES || self.scanline == self.pre_render_scanline();
+        if rendering_line && self.mask.rendering_enabled() {
+            self.clock_background();
+            self.clock_sprites();
+        }
+        if self.scanline < VISIBLE_SCANLINES && (1..=SCREEN_WIDTH as u16).contains(&self.dot) {
+            self.output_pixel();
+        }
+
+        match (self.scanline, self.dot) {
+            (scanline, 1) if scanline == self.region.vblank_scanline() => {
+                if !self.suppress_vblank {
+                    self.status.insert(StatusRegister::VBLANK_STARTED);
+                    self.nmi_pending = self.ctrl.generate_vblank_nmi();
+                }
+                self.suppress_vblank = false;
+            }
+            (scanline, 1) if scanline == self.pre_render_scanline() => {
+                self.status.remove(
+                    StatusRegister::VBLANK_STARTED | StatusRegister::SPRITE_ZERO_HIT | StatusRegister::SPRITE_OVERFLOW,
+                );
+            }
+            _ => {}
+        }
+    }
+
+    fn output_pixel(&mut self) {
+        let x = (self.dot - 1) as usize;
+        let (bg_pixel, bg_palette) = self.background_pixel(x);
+        let sprite = self.sprite_pixel(x);
+        if let Some((_, _, _, true)) = sprite {
+            // https://www.nesdev.org/wiki/PPU_OAM#Sprite_zero_hits, never on the last column
+            if bg_pixel != 0 && x != SCREEN_WIDTH - 1 {
+                self.status.insert(StatusRegister::SPRITE_ZERO_HIT);
+            }
+        }
+        let address = match sprite {
+            Some((pixel, palette, behind, _)) if bg_pixel == 0 || !behind => {
+                PALETTE_RAM + palette as u16 * 4 + pixel as u16
+            }
+            _ if bg_pixel != 0 => PALETTE_RAM + bg_palette as u16 * 4 + bg_pixel as u16,
+            // with rendering off the backdrop is whatever palette entry v points at
+            _ if !self.mask.rendering_enabled() && self.v & 0x3F00 == PALETTE_RAM => self.v,
+            _ => PALETTE_RAM,
+        };
+        let mut emphasis = (self.mask.bits() as u16 & 0b1110_0000) << 1;
+        if self.region != Region::Ntsc {
+            // the PAL PPU swaps the red and green emphasis bits
+            emphasis = (emphasis & 0x100) | ((emphasis & 0x40) << 1) | ((emphasis & 0x80) >> 1);
+        }
+        self.frame_buffer[self.scanline as usize * SCREEN_WIDTH + x] = self.read_palette(address) as u16 | emphasis;
+    }
+
+    pub fn set_mapper(&mut self, mapper: MapperRef) {
+        self.mapper = Some(mapper);
+    }
+
+    /// Frame layout of the machine: scanlines per frame, vblank start and the NTSC odd frame skip
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+    }
+
+    /// Last line of the frame, where the PPU fetches the first tiles of the next frame
+    fn pre_render_scanline(&self) -> u16 {
+        self.region.scanlines_per_frame() - 1
+    }
+
+    /// CPU read of $2000-$2007 (already mirrored down)
+    pub fn read_register(&mut self, address: u16) -> u8 {
+        let io_latch = self.decayed_io_latch();
+        match address {
+            0x2002 => {
+                // https://www.nesdev.org/wiki/PPU_frame_timing#VBL_Flag_Timing
+                if self.scanline == self.region.vblank_scanline() {
+                    match self.dot {
+                        0 => self.suppress_vblank = true,
+                        1 | 2 => self.nmi_pending = false,
+                        _ => {}
+                    }
+                }
+                let status = self.status.bits();
+                self.status.remove(StatusRegister::VBLANK_STARTED);
+                self.write_toggle = false;
+                self.refresh_io_latch(status, 0b1110_0000)
+            }
+            0x2004 => {
+                let value = self.oam[self.oam_addr as usize];
+                self.refresh_io_latch(value, 0xFF)
+            }
+            0x2007 => {
+                // palette entries are 6 bits, the top two come from the latch
+                let driven = if self.v & 0x3FFF >= PALETTE_RAM { 0b0011_1111 } else { 0xFF };
+                let value = self.read_data();
+                self.refresh_io_latch(value, driven)
+            }
+            // write-only registers
+            _ => io_latch,
+        }
+    }
+
+    /// Drives the bits of `mask` with `value` and returns the whole latch, as the CPU sees it
+    fn refresh_io_latch(&mut self, value: u8, mask: u8) -> u8 {
+        self.io_latch = (self.io_latch & !mask) | (value & mask);
+        for bit in 0..8 {
+            if mask & (1 << bit) != 0 {
+                self.io_latch_refreshed[bit] = self.frame;
+            }
+        }
+        self.io_latch
+    }
+
+    fn decayed_io_latch(&mut self) -> u8 {
+        for bit in 0..8 {
+            if self.frame - self.io_latch_refreshed[bit] >= IO_LATCH_DECAY_FRAMES {
+                self.io_latch &= !(1 << bit);
+            }
+        }
+        self.io_latch
+    }
+
+    /// CPU write to $2000-$2007 (already mirrored down)
+    pub fn write_register(&mut self, address: u16, value: u8) {
+        self.decayed_io_latch();
+        self.refresh_io_latch(value, 0xFF);
+        match address {
+            0x2000 => {
+                let nmi_was_enabled = self.ctrl.generate_vblank_nmi();
+                self.ctrl = ControlRegister::from_bits_truncate(value);
+                // enabling NMI during vblank fires it right away, disabling it drops one not yet taken
+                if !self.ctrl.generate_vblank_nmi() {
+                    self.nmi_pending = false;
+                } else if !nmi_was_enabled && self.status.contains(StatusRegister::VBLANK_STARTED) {
+                    self.nmi_pending = true;
+                }
+                self.t = (self.t & !0x0C00) | ((value as u16 & 0b11) << 10);
+            }
+            0x2001 => self.mask = MaskRegister::from_bits_truncate(value),
+            0x2003 => self.oam_addr = value,
+            0x2004 => {
+                self.oam[self.oam_addr as usize] = value;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x2005 => {
+                if !self.write_toggle {
+                    self.t = (self.t & !0x001F) | (value as u16 >> 3);
+                    self.fine_x = value & 0b111;
+                } else {
+                    self.t = (self.t & !0x73E0) | ((value as u16 & 0b111) << 12) | ((value as u16 & 0xF8) << 2);
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            0x2006 => {
+                if !self.write_toggle {
+                    self.t = (self.t & 0x00FF) | ((value as u16 & 0x3F) << 8);
+                } else {
+                    self.t = (self.t & 0xFF00) | value as u16;
+                    self.v = self.t;
+                }
+                self.write_toggle = !self.write_toggle;
+            }
+            0x2007 => {
+                self.write_vram(self.v & 0x3FFF, value);
+                self.increment_vram_addr();
+            }
+            // $2002 is read-only
+            _ => {}
+        }
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let address = self.v & 0x3FFF;
+        self.increment_vram_addr();
+        if address >= PALETTE_RAM {
+            // palette reads are immediate, the buffer gets the nametable byte "underneath"
+            self.read_buffer = self.read_vram(address - 0x1000);
+            self.read_palette(address)
+        } else {
+            let value = self.read_buffer;
+            self.read_buffer = self.read_vram(address);
+            value
+        }
+    }
+
+    fn increment_vram_addr(&mut self) {
+        self.v = self.v.wrapping_add(self.ctrl.vram_addr_increment()) & 0x7FFF;
+    }
+
+    /// Offset in `vram` of a nametable address, following the mapper's mirroring
+    fn nametable_offset(&self, address: u16) -> usize {
+        let address = (address - NAMETABLES) as usize & 0x0FFF;
+        let table = address / NAMETABLE_SIZE;
+        let page = match &self.mapper {
+            Some(mapper) => mapper.borrow().nametable_page(table),
+            None => table & 1,
+        };
+        page * NAMETABLE_SIZE + (address & (NAMETABLE_SIZE - 1))
+    }
+
+    fn palette_index(address: u16) -> usize {
+        let index = (address & 0x1F) as usize;
+        // $3F10/$3F14/$3F18/$3F1C mirror the backdrop entries $3F00/$3F04/$3F08/$3F0C
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+            _ => index,
+        }
+    }
+
+    /// Greyscale keeps only the brightness column of the colour, PPUDATA reads included
+    fn read_palette(&self, address: u16) -> u8 {
+        let colour = self.palette[Ppu::palette_index(address)];
+        if self.mask.contains(MaskRegister::GREYSCALE) {
+            colour & 0x30
+        } else {
+            colour
+        }
+    }
+
+    /// PPU bus read, $0000-$3FFF
+    pub fn read_vram(&mut self, address: u16) -> u8 {
+        let address = address & 0x3FFF;
+        if let Some(mapper) = &self.mapper {
+            mapper.borrow_mut().ppu_address(address);
+        }
+        match address {
+            0..=PATTERN_TABLES_END => match &self.mapper {
+                Some(mapper) => mapper.borrow_mut().chr_read(address),
+                None => 0,
+            },
+            NAMETABLES..=NAMETABLES_MIRRORS_END => {
+                if let Some(mapper) = &self.mapper {
+                    if let Some(value) = mapper.borrow_mut().nametable_read(address) {
+                        return value;
+                    }
+                }
+                self.vram[self.nametable_offset(address)]
+            }
+            _ => self.read_palette(address),
+        }
+    }
+
+    /// PPU bus write, $0000-$3FFF
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        let address = address & 0x3FFF;
+        match address {
+            0..=PATTERN_TABLES_END => {
+                if let Some(mapper) = &self.mapper {
+                    mapper.borrow_mut().chr_write(address, value);
+                }
+            }
+            NAMETABLES..=NAMETABLES_MIRRORS_END => {
+                if let Some(mapper) = &self.mapper {
+                    if mapper.borrow_mut().nametable_write(address, value) {
+                        return;
+                    }
+                }
+                let offset = self.nametable_offset(address);
+                self.vram[offset] = value;
+            }
+            _ => self.palette[Ppu::palette_index(address)] = value & 0b0011_1111,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test::ines_image;
+    use crate::cartridge::Cartridge;
+    use crate::mapper;
+
+    fn ppu(flags_6: u8) -> Ppu {
+        let mut ppu = Ppu::new();
+        let cartridge = Cartridge::from_ines(&ines_image(0, 1, 0, flags_6)).unwrap();
+        ppu.set_mapper(mapper::create(&cartridge).unwrap());
+        ppu
+    }
+
+    fn set_address(ppu: &mut Ppu, address: u16) {
+        ppu.write_register(0x2006, (address >> 8) as u8);
+        ppu.write_register(0x2006, address as u8);
+    }
+
+    #[test]
+    fn test_ppudata_read_buffer() {
+        let mut ppu = ppu(0);
+        ppu.write_vram(0x2305, 0x66);
+        ppu.write_vram(0x2306, 0x77);
+        set_address(&mut ppu, 0x2305);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x66);
+        assert_eq!(ppu.read_register(0x2007), 0x77);
+    }
+
+    #[test]
+    fn test_ppudata_increment_32() {
+        let mut ppu = ppu(0);
+        ppu.write_register(0x2000, 0b100);
+        set_address(&mut ppu, 0x2000);
+        ppu.write_register(0x2007, 1);
+        ppu.write_register(0x2007, 2);
+        assert_eq!(ppu.read_vram(0x2000), 1);
+        assert_eq!(ppu.read_vram(0x2020), 2);
+    }
+
+    #[test]
+    fn test_palette_reads_are_immediate_and_mirrored() {
+        let mut ppu = ppu(0);
+        ppu.write_vram(0x2F10, 0x55);
+        set_address(&mut ppu, 0x3F10);
+        ppu.write_register(0x2007, 0x2A);
+        set_address(&mut ppu, 0x3F00);
+        assert_eq!(ppu.read_register(0x2007), 0x2A);
+        set_address(&mut ppu, 0x3F10);
+        assert_eq!(ppu.read_register(0x2007), 0x2A);
+        // the buffer got the nametable byte below the palette
+        set_address(&mut ppu, 0x0000);
+        assert_eq!(ppu.read_register(0x2007), 0x55);
+    }
+
+    #[test]
+    fn test_nametable_mirroring() {
+        let mut horizontal = ppu(0);
+        horizontal.write_vram(0x2001, 0x11);
+        horizontal.write_vram(0x2801, 0x22);
+        assert_eq!(horizontal.read_vram(0x2401), 0x11);
+        assert_eq!(horizontal.read_vram(0x2C01), 0x22);
+        assert_eq!(horizontal.read_vram(0x3401), 0x11);
+
+        let mut vertical = ppu(1);
+        vertical.write_vram(0x2001, 0x11);
+        vertical.write_vram(0x2401, 0x22);
+        assert_eq!(vertical.read_vram(0x2801), 0x11);
+        assert_eq!(vertical.read_vram(0x2C01), 0x22);
+    }
+
+    #[test]
+    fn test_mirroring_follows_the_mapper() {
+        let mut ppu = Ppu::new();
+        let cartridge = Cartridge::from_ines(&ines_image(7, 2, 0, 0)).unwrap();
+        let mapper = mapper::create(&cartridge).unwrap();
+        ppu.set_mapper(mapper.clone());
+        ppu.write_vram(0x2C01, 0x11);
+        assert_eq!(ppu.read_vram(0x2001), 0x11);
+        mapper.borrow_mut().cpu_write(0x8000, 0b1_0000);
+        assert_eq!(ppu.read_vram(0x2001), 0);
+        ppu.write_vram(0x2401, 0x22);
+        mapper.borrow_mut().cpu_write(0x8000, 0);
+        assert_eq!(ppu.read_vram(0x2801), 0x11);
+    }
+
+    #[test]
+    fn test_four_screen_vram() {
+        let mut ppu = ppu(0b1000);
+        for table in 0..4 {
+            ppu.write_vram(0x2000 + table * 0x400, table as u8 + 1);
+        }
+        for table in 0..4 {
+            assert_eq!(ppu.read_vram(0x2000 + table * 0x400), table as u8 + 1);
+        }
+    }
+
+    #[test]
+    fn test_status_read_clears_vblank_and_toggle() {
+        let mut ppu = ppu(0);
+        ppu.status.insert(StatusRegister::VBLANK_STARTED);
+        ppu.write_register(0x2006, 0x21);
+        assert_eq!(ppu.read_register(0x2002) >> 7, 1);
+        assert_eq!(ppu.read_register(0x2002) >> 7, 0);
+        set_address(&mut ppu, 0x2305);
+        assert_eq!(ppu.v, 0x2305);
+    }
+
+    fn run_to(ppu: &mut Ppu, scanline: u16, dot: u16) {
+        while (ppu.scanline, ppu.dot) != (scanline, dot) {
+            ppu.tick();
+        }
+    }
+
+    #[test]
+    fn test_vblank_and_nmi() {
+        let mut ppu = ppu(0);
+        ppu.write_register(0x2000, 0x80);
+        run_to(&mut ppu, 241, 0);
+        ppu.tick();
+        assert!(ppu.status.contains(StatusRegister::VBLANK_STARTED));
+        assert!(ppu.poll_nmi());
+        assert!(!ppu.poll_nmi());
+
+        // enabling NMI again during vblank fires another one
+        ppu.write_register(0x2000, 0);
+        ppu.write_register(0x2000, 0x80);
+        assert!(ppu.poll_nmi());
+
+        run_to(&mut ppu, 261, 1);
+        assert!(!ppu.status.contains(StatusRegister::VBLANK_STARTED));
+    }
+
+    #[test]
+    fn test_status_read_races_vblank() {
+        let mut ppu = ppu(0);
+        ppu.write_register(0x2000, 0x80);
+        run_to(&mut ppu, 241, 0);
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0);
+        ppu.tick();
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0);
+        assert!(!ppu.poll_nmi());
+
+        run_to(&mut ppu, 0, 0);
+        run_to(&mut ppu, 241, 1);
+        assert_eq!(ppu.read_register(0x2002) & 0x80, 0x80);
+        assert!(!ppu.poll_nmi());
+    }
+
+    #[test]
+    fn test_odd_frames_skip_a_dot_when_rendering() {
+        let mut ppu = ppu(0);
+        let frame_length = |ppu: &mut Ppu| {
+            let frame = ppu.frame;
+            let mut dots = 0;
+            while ppu.frame == frame {
+                ppu.tick();
+                dots += 1;
+            }
+            dots
+        };
+        frame_length(&mut ppu);
+        assert_eq!(frame_length(&mut ppu), 341 * 262);
+        assert_eq!(frame_length(&mut ppu), 341 * 262);
+        ppu.write_register(0x2001, 0b1000);
+        assert_eq!(frame_length(&mut ppu), 341 * 262 - 1);
+        assert_eq!(frame_length(&mut ppu), 341 * 262);
+    }
+
+    #[test]
+    fn test_pal_and_dendy_frames() {
+        for (region, vblank) in [(Region::Pal, 241), (Region::Dendy, 291)] {
+            let mut ppu = ppu(0);
+            ppu.set_region(region);
+            ppu.write_register(0x2001, 0b1000);
+            run_to(&mut ppu, vblank, 1);
+            assert!(ppu.status.contains(StatusRegister::VBLANK_STARTED));
+            run_to(&mut ppu, 311, 1);
+            assert!(!ppu.status.contains(StatusRegister::VBLANK_STARTED));
+            // no odd frame skip
+            run_to(&mut ppu, 0, 0);
+            let mut dots = 0;
+            while ppu.frame() == 1 {
+                ppu.tick();
+                dots += 1;
+            }
+            assert_eq!(dots, 341 * 312);
+        }
+    }
+
+    fn run_frame(ppu: &mut Ppu) {
+        let frame = ppu.frame;
+        while ppu.frame == frame {
+            ppu.tick();
+        }
+    }
+
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> u16 {
+        ppu.frame_buffer()[y * SCREEN_WIDTH + x]
+    }
+
+    /// Columns alternate between a solid tile (1) and an empty one (2), palette 0
+    fn striped_background() -> Ppu {
+        let mut ppu = ppu(0);
+        for row in 0..8 {
+            ppu.write_vram(0x0010 + row, 0xFF);
+        }
+        for tile in 0..960 {
+            ppu.write_vram(0x2000 + tile, if tile % 2 == 0 { 1 } else { 2 });
+        }
+        ppu.write_vram(0x3F00, 0x0F);
+        ppu.write_vram(0x3F01, 0x16);
+        ppu.write_register(0x2001, 0b0000_1010);
+        ppu
+    }
+
+    #[test]
+    fn test_background_rendering() {
+        let mut ppu = striped_background();
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 0x16);
+        assert_eq!(pixel(&ppu, 7, 0), 0x16);
+        assert_eq!(pixel(&ppu, 8, 0), 0x0F);
+        assert_eq!(pixel(&ppu, 255, 239), 0x0F);
+
+        ppu.write_register(0x2005, 4);
+        ppu.write_register(0x2005, 0);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 3, 10), 0x16);
+        assert_eq!(pixel(&ppu, 4, 10), 0x0F);
+        assert_eq!(pixel(&ppu, 12, 10), 0x16);
+
+        // left column clipping shows the backdrop
+        ppu.write_register(0x2001, 0b0000_1000);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 3, 10), 0x0F);
+        assert_eq!(pixel(&ppu, 12, 10), 0x16);
+    }
+
+    #[test]
+    fn test_mid_frame_scroll_change() {
+        let mut ppu = striped_background();
+        run_frame(&mut ppu);
+        run_to(&mut ppu, 100, 200);
+        ppu.read_register(0x2002);
+        ppu.write_register(0x2005, 8);
+        ppu.write_register(0x2005, 0);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 100), 0x16);
+        assert_eq!(pixel(&ppu, 0, 101), 0x0F);
+        assert_eq!(pixel(&ppu, 8, 101), 0x16);
+    }
+
+    /// Sprite tile 3 is a left-half block, sprite palette 0 colour 1 is $21
+    fn sprite_ppu() -> Ppu {
+        let mut ppu = striped_background();
+        for row in 0..8 {
+            ppu.write_vram(0x0030 + row, 0xF0);
+        }
+        ppu.write_vram(0x3F11, 0x21);
+        ppu.oam = [0xFF; 256];
+        ppu.write_register(0x2001, 0b0001_1110);
+        ppu
+    }
+
+    fn set_sprite(ppu: &mut Ppu, index: usize, y: u8, tile: u8, attributes: u8, x: u8) {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&[y, tile, attributes, x]);
+    }
+
+    #[test]
+    fn test_sprite_rendering() {
+        let mut ppu = sprite_ppu();
+        // over the empty stripe, then behind the solid one, then flipped
+        set_sprite(&mut ppu, 0, 19, 3, 0, 8);
+        set_sprite(&mut ppu, 1, 39, 3, 0b0010_0000, 0);
+        set_sprite(&mut ppu, 2, 59, 3, 0b0100_0000, 8);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 8, 19), 0x0F);
+        assert_eq!(pixel(&ppu, 8, 20), 0x21);
+        assert_eq!(pixel(&ppu, 11, 27), 0x21);
+        assert_eq!(pixel(&ppu, 12, 27), 0x0F);
+        assert_eq!(pixel(&ppu, 8, 28), 0x0F);
+        assert_eq!(pixel(&ppu, 3, 40), 0x16);
+        assert_eq!(pixel(&ppu, 11, 60), 0x0F);
+        assert_eq!(pixel(&ppu, 12, 60), 0x21);
+    }
+
+    #[test]
+    fn test_8x16_sprites_and_vertical_flip() {
+        let mut ppu = sprite_ppu();
+        // tile 3 -> table $1000, tiles 2 and 3; only the bottom one has pixels
+        for row in 0..8 {
+            ppu.write_vram(0x1030 + row, 0xFF);
+        }
+        ppu.write_register(0x2000, 0b0010_0000);
+        set_sprite(&mut ppu, 0, 99, 3, 0, 9);
+        set_sprite(&mut ppu, 1, 149, 3, 0b1000_0000, 9);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 9, 107), 0x0F);
+        assert_eq!(pixel(&ppu, 9, 108), 0x21);
+        assert_eq!(pixel(&ppu, 9, 115), 0x21);
+        assert_eq!(pixel(&ppu, 9, 116), 0x0F);
+        assert_eq!(pixel(&ppu, 9, 150), 0x21);
+        assert_eq!(pixel(&ppu, 9, 158), 0x0F);
+    }
+
+    #[test]
+    fn test_sprite_zero_hit() {
+        let mut ppu = sprite_ppu();
+        set_sprite(&mut ppu, 0, 49, 3, 0, 16);
+        run_frame(&mut ppu);
+        run_to(&mut ppu, 50, 16);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+        run_to(&mut ppu, 50, 18);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+        run_to(&mut ppu, 261, 2);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT));
+
+        // not over a transparent background pixel, nor in a clipped left column, nor at x=255
+        let hit_at = |x: u8, mask: u8| {
+            let mut ppu = sprite_ppu();
+            for tile in 0..960 {
+                ppu.write_vram(0x2000 + tile, 1);
+            }
+            ppu.write_register(0x2001, mask);
+            set_sprite(&mut ppu, 0, 49, 3, 0, x);
+            run_to(&mut ppu, 100, 0);
+            ppu.status.contains(StatusRegister::SPRITE_ZERO_HIT)
+        };
+        assert!(hit_at(0, 0b0001_1110));
+        assert!(!hit_at(0, 0b0001_1010));
+        assert!(!hit_at(0, 0b0001_1100));
+        assert!(hit_at(254, 0b0001_1110));
+        assert!(!hit_at(255, 0b0001_1110));
+    }
+
+    #[test]
+    fn test_sprite_limit_and_overflow() {
+        let mut ppu = sprite_ppu();
+        for index in 0..9 {
+            set_sprite(&mut ppu, index, 29, 3, 0, index as u8 * 16 + 8);
+        }
+        run_to(&mut ppu, 29, 0);
+        assert!(!ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+        run_to(&mut ppu, 30, 0);
+        assert!(ppu.status.contains(StatusRegister::SPRITE_OVERFLOW));
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 7 * 16 + 8, 30), 0x21);
+        assert_eq!(pixel(&ppu, 8 * 16 + 8, 30), 0x0F);
+
+        // after 8 sprites the PPU also steps through the bytes of each entry, so here it ends up
+        // comparing sprite 11's X against the scanline
+        let overflow = |x: u8| {
+            let mut ppu = sprite_ppu();
+            for index in 0..8 {
+                set_sprite(&mut ppu, index, 29, 3, 0, 0);
+            }
+            for index in 8..11 {
+                set_sprite(&mut ppu, index, 200, 0, 0, 200);
+            }
+            set_sprite(&mut ppu, 11, 200, 0, 0, x);
+            run_to(&mut ppu, 100, 0);
+            ppu.status.contains(StatusRegister::SPRITE_OVERFLOW)
+        };
+        assert!(overflow(29));
+        assert!(!overflow(200));
+    }
+
+    #[test]
+    fn test_greyscale_and_emphasis() {
+        let mut ppu = striped_background();
+        ppu.write_register(0x2001, 0b1010_1011);
+        run_frame(&mut ppu);
+        run_frame(&mut ppu);
+        assert_eq!(pixel(&ppu, 0, 0), 0x140 | 0x10);
+        assert_eq!(pixel(&ppu, 8, 0), 0x140);
+        set_address(&mut ppu, 0x3F01);
+        assert_eq!(ppu.read_register(0x2007), 0x10);
+    }
+
+    #[test]
+    fn test_open_bus() {
+        let mut ppu = ppu(0);
+        ppu.write_register(0x2003, 0b0001_1111);
+        assert_eq!(ppu.read_register(0x2000), 0b0001_1111);
+        assert_eq!(ppu.read_register(0x2002) & 0b0001_1111, 0b0001_1111);
+
+        ppu.write_vram(0x3F01, 0x3F);
+        set_address(&mut ppu, 0x3F01);
+        ppu.write_register(0x2003, 0x80);
+        assert_eq!(ppu.read_register(0x2007), 0x80 | 0x3F);
+        assert_eq!(ppu.read_register(0x2005), 0x80 | 0x3F);
+
+        // bits fade after about 600ms without being driven, each on its own
+        for _ in 0..20 {
+            run_frame(&mut ppu);
+        }
+        set_address(&mut ppu, 0x3F01);
+        ppu.read_register(0x2007);
+        for _ in 0..20 {
+            run_frame(&mut ppu);
+        }
+        assert_eq!(ppu.read_register(0x2003), 0x3F);
+        for _ in 0..20 {
+            run_frame(&mut ppu);
+        }
+        assert_eq!(ppu.read_register(0x2003), 0);
+    }
+
+    #[test]
+    fn test_oam_data() {
+        let mut ppu = ppu(0);
+        ppu.write_register(0x2003, 0x10);
+        ppu.write_register(0x2004, 0x66);
+        ppu.write_register(0x2004, 0x77);
+        ppu.write_register(0x2003, 0x11);
+        assert_eq!(ppu.read_register(0x2004), 0x77);
+        assert_eq!(ppu.oam[0x10], 0x66);
+    }
+
+    #[test]
+    // t grouped as fine Y, nametable, coarse Y, coarse X
+    #[allow(clippy::unusual_byte_groupings)]
+    fn test_scroll_writes_fill_t() {
+        let mut ppu = ppu(0);
+        ppu.write_register(0x2000, 0b10);
+        ppu.write_register(0x2005, 0b0111_1101);
+        ppu.write_register(0x2005, 0b0101_1110);
+        assert_eq!(ppu.fine_x, 0b101);
+        assert_eq!(ppu.t, 0b110_10_01011_01111);
+    }
+}
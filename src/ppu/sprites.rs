@@ -0,0 +1,178 @@
+use super::registers::{MaskRegister, StatusRegister};
+use super::Ppu;
+
+const MAX_SPRITES_PER_LINE: usize = 8;
+const SPRITE_FETCHES: u16 = 257;
+const SPRITE_FETCHES_END: u16 = 320;
+
+const ATTRIBUTE_PALETTE: u8 = 0b0000_0011;
+const ATTRIBUTE_BEHIND_BACKGROUND: u8 = 0b0010_0000;
+const ATTRIBUTE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const ATTRIBUTE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+/// A sprite on the line being drawn, with its pattern already flipped horizontally if needed
+#[derive(Default, Clone, Copy)]
+struct LineSprite {
+    x: u8,
+    attributes: u8,
+    pattern_low: u8,
+    pattern_high: u8,
+}
+
+/// Sprite evaluation and fetches https://www.nesdev.org/wiki/PPU_sprite_evaluation
+///
+/// Evaluation runs in one go at the end of the visible part of a line and the eight pattern fetches happen
+/// at their real dots (257-320), so mappers watching the bus see the usual pattern.
+#[derive(Default)]
+pub(super) struct Sprites {
+    secondary_oam: [u8; 4 * MAX_SPRITES_PER_LINE],
+    /// Sprites found for the next line
+    found: usize,
+    sprite_zero_found: bool,
+
+    line: [LineSprite; MAX_SPRITES_PER_LINE],
+    line_count: usize,
+    sprite_zero_on_line: bool,
+}
+
+impl Ppu {
+    /// Runs on the visible and pre-render lines while rendering is enabled
+    pub(super) fn clock_sprites(&mut self) {
+        if !(SPRITE_FETCHES..=SPRITE_FETCHES_END).contains(&self.dot) {
+            return;
+        }
+        self.oam_addr = 0;
+        if self.dot == SPRITE_FETCHES {
+            self.evaluate_sprites();
+            self.sprites.line_count = self.sprites.found;
+            self.sprites.sprite_zero_on_line = self.sprites.sprite_zero_found;
+        }
+
+        let slot = ((self.dot - SPRITE_FETCHES) / 8) as usize;
+        match (self.dot - SPRITE_FETCHES) % 8 {
+            // two garbage nametable fetches
+            0 | 2 => {
+                self.read_vram(0x2000 | (self.v & 0x0FFF));
+            }
+            4 => {
+                let address = self.sprite_pattern_address(slot);
+                let pattern = self.read_vram(address);
+                self.set_sprite_pattern(slot, pattern, false);
+            }
+            6 => {
+                let address = self.sprite_pattern_address(slot) + 8;
+                let pattern = self.read_vram(address);
+                self.set_sprite_pattern(slot, pattern, true);
+            }
+            _ => {}
+        }
+    }
+
+    /// Fills secondary OAM with the sprites of the next line, including the hardware bug that makes
+    /// the overflow flag check the wrong OAM bytes once 8 sprites were found
+    fn evaluate_sprites(&mut self) {
+        let pre_render = self.scanline == self.pre_render_scanline();
+        let sprites = &mut self.sprites;
+        sprites.secondary_oam = [0xFF; 4 * MAX_SPRITES_PER_LINE];
+        sprites.found = 0;
+        sprites.sprite_zero_found = false;
+        if pre_render {
+            return;
+        }
+
+        let height = self.ctrl.sprite_height();
+        let scanline = self.scanline;
+        let in_range = |y: u8| scanline.wrapping_sub(y as u16) < height;
+        let mut n = 0;
+        while n < 64 && sprites.found < MAX_SPRITES_PER_LINE {
+            if in_range(self.oam[n * 4]) {
+                let slot = sprites.found * 4;
+                sprites.secondary_oam[slot..slot + 4].copy_from_slice(&self.oam[n * 4..n * 4 + 4]);
+                sprites.sprite_zero_found |= n == 0;
+                sprites.found += 1;
+            }
+            n += 1;
+        }
+        let mut m = 0;
+        while n < 64 {
+            if in_range(self.oam[n * 4 + m]) {
+                self.status.insert(StatusRegister::SPRITE_OVERFLOW);
+                break;
+            }
+            n += 1;
+            m = (m + 1) % 4;
+        }
+    }
+
+    /// Slots without a sprite fetch tile $FF like the real PPU
+    fn sprite_pattern_address(&self, slot: usize) -> u16 {
+        let sprite = &self.sprites.secondary_oam[slot * 4..slot * 4 + 4];
+        let (y, tile, attributes) = (sprite[0], sprite[1] as u16, sprite[2]);
+        let height = self.ctrl.sprite_height();
+        let mut row = if slot < self.sprites.found {
+            self.scanline.wrapping_sub(y as u16)
+        } else {
+            0
+        };
+        if attributes & ATTRIBUTE_FLIP_VERTICAL != 0 && slot < self.sprites.found {
+            row = height - 1 - row;
+        }
+        if height == 16 {
+            let table = (tile & 1) * 0x1000;
+            let tile = (tile & 0xFE) + if row >= 8 { 1 } else { 0 };
+            table + tile * 16 + (row & 0b111)
+        } else {
+            self.ctrl.sprite_pattern_addr() + tile * 16 + row
+        }
+    }
+
+    fn set_sprite_pattern(&mut self, slot: usize, pattern: u8, high: bool) {
+        if slot >= self.sprites.line_count {
+            return;
+        }
+        let attributes = self.sprites.secondary_oam[slot * 4 + 2];
+        let pattern = if attributes & ATTRIBUTE_FLIP_HORIZONTAL != 0 {
+            pattern.reverse_bits()
+        } else {
+            pattern
+        };
+        let sprite = &mut self.sprites.line[slot];
+        sprite.x = self.sprites.secondary_oam[slot * 4 + 3];
+        sprite.attributes = attributes;
+        if high {
+            sprite.pattern_high = pattern;
+        } else {
+            sprite.pattern_low = pattern;
+        }
+    }
+
+    /// First opaque sprite pixel at screen column `x`: pixel value (1-3), palette (4-7), whether it sits
+    /// behind the background and whether it is sprite 0
+    pub(super) fn sprite_pixel(&self, x: usize) -> Option<(u8, u8, bool, bool)> {
+        let clipped = x < 8 && !self.mask.contains(MaskRegister::LEFTMOST_8PXL_SPRITE);
+        if !self.mask.show_sprites() || clipped {
+            return None;
+        }
+        let sprites = &self.sprites;
+        sprites.line[..sprites.line_count]
+            .iter()
+            .enumerate()
+            .find_map(|(slot, sprite)| {
+                let column = x.wrapping_sub(sprite.x as usize);
+                if column >= 8 {
+                    return None;
+                }
+                let bit = 7 - column;
+                let pixel = ((sprite.pattern_high >> bit) & 1) << 1 | ((sprite.pattern_low >> bit) & 1);
+                if pixel == 0 {
+                    return None;
+                }
+                Some((
+                    pixel,
+                    4 + (sprite.attributes & ATTRIBUTE_PALETTE),
+                    sprite.attributes & ATTRIBUTE_BEHIND_BACKGROUND != 0,
+                    slot == 0 && sprites.sprite_zero_on_line,
+                ))
+            })
+    }
+}
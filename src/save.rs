@@ -0,0 +1,104 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::mapper::MapperRef;
+
+/// Roughly five seconds of NTSC CPU time between periodic flushes
+const FLUSH_INTERVAL_CYCLES: usize = 1_789_773 * 5;
+
+/// Keeps battery backed PRG RAM (or FDS disk contents, see `Mapper::save_data`) in sync with a `.sav` file on disk.
+///
+/// The file is read into the cartridge when attached, written back every few seconds of emulated time
+/// when the RAM changed, and once more when dropped.
+pub struct SaveFile {
+    path: PathBuf,
+    mapper: MapperRef,
+    saved: Vec<u8>,
+    cycles_since_flush: usize,
+}
+
+impl SaveFile {
+    /// `game.nes` saves to `game.sav` in the same directory
+    pub fn path_for(rom_path: &Path) -> PathBuf {
+        rom_path.with_extension("sav")
+    }
+
+    pub fn open(path: PathBuf, mapper: MapperRef) -> Result<SaveFile, String> {
+        if path.exists() {
+            let data = fs::read(&path).map_err(|e| format!("Can not read save file {}: {}", path.display(), e))?;
+            load_into(&mapper, &data);
+        }
+        let saved = mapper.borrow().save_data().to_vec();
+        Ok(SaveFile { path, mapper, saved, cycles_since_flush: 0 })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Writes the save file if the RAM changed since the last flush
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.cycles_since_flush = 0;
+        let mapper = self.mapper.borrow();
+        let data = mapper.save_data();
+        if data == &self.saved[..] {
+            return Ok(());
+        }
+        fs::write(&self.path, data).map_err(|e| format!("Can not write save file {}: {}", self.path.display(), e))?;
+        self.saved = data.to_vec();
+        Ok(())
+    }
+
+    pub fn tick(&mut self, cycles: usize) {
+        self.cycles_since_flush += cycles;
+        if self.cycles_since_flush >= FLUSH_INTERVAL_CYCLES {
+            if let Err(e) = self.flush() {
+                println!("{}", e);
+            }
+        }
+    }
+}
+
+impl Drop for SaveFile {
+    fn drop(&mut self) {
+        if let Err(e) = self.flush() {
+            println!("{}", e);
+        }
+    }
+}
+
+/// Copies save data into the mapper, ignoring whatever does not fit
+pub fn load_into(mapper: &MapperRef, data: &[u8]) {
+    let mut mapper = mapper.borrow_mut();
+    let ram = mapper.save_data_mut();
+    let len = std::cmp::min(ram.len(), data.len());
+    ram[..len].copy_from_slice(&data[..len]);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::test::ines_image;
+    use crate::cartridge::Cartridge;
+    use crate::mapper;
+
+    #[test]
+    fn test_save_round_trip() {
+        let path = std::env::temp_dir().join(format!("rust-nes-save-test-{}.sav", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let cartridge = Cartridge::from_ines(&ines_image(0, 1, 1, 0b10)).unwrap();
+
+        let mapper = mapper::create(&cartridge).unwrap();
+        let mut save = SaveFile::open(path.clone(), mapper.clone()).unwrap();
+        mapper.borrow_mut().cpu_write(0x6000, 0x42);
+        mapper.borrow_mut().cpu_write(0x7FFF, 0x24);
+        drop(save);
+
+        let mapper = mapper::create(&cartridge).unwrap();
+        save = SaveFile::open(path.clone(), mapper.clone()).unwrap();
+        assert_eq!(mapper.borrow_mut().cpu_read(0x6000), 0x42);
+        assert_eq!(mapper.borrow_mut().cpu_read(0x7FFF), 0x24);
+        assert_eq!(fs::read(save.path()).unwrap().len(), 0x2000);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use crate::cpu::{AddressingMode, Cpu};
+use crate::opscode;
+
+/// One line in the nestest.log format (Nintendulator's), describing the instruction at PC before it runs:
+///
+/// ```text
+/// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+/// ```
+///
+/// Memory is read through `Bus::peek`, so tracing doesn't disturb the program. The I/O registers show
+/// as $FF, like in nestest.log.
+pub fn trace(cpu: &Cpu) -> String {
+    let bus = &cpu.bus;
+    let peek = |address: u16| bus.peek(address).unwrap_or(0xFF);
+    // pointers in the zero page wrap around it
+    let peek_zero_page_u16 =
+        |address: u8| u16::from_le_bytes([peek(address as u16), peek(address.wrapping_add(1) as u16)]);
+
+    let pc = cpu.program_counter;
+    let code = peek(pc);
+    let (mnemonic, len, mode) = match opscode::OPCODES_MAP.get(&code) {
+        Some(opcode) => (opcode.mnemonic, opcode.len, &opcode.mode),
+        None => ("???", 1, &AddressingMode::NoneAddressing),
+    };
+    let bytes: Vec<u8> = (0..len as u16).map(|i| peek(pc.wrapping_add(i))).collect();
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+    let (x, y) = (cpu.register_x(), cpu.register_y());
+
+    let operand = match mode {
+        AddressingMode::Immediate => format!("#${:02X}", byte),
+        AddressingMode::ZeroPage => format!("${:02X} = {:02X}", byte, peek(byte as u16)),
+        AddressingMode::ZeroPageX => {
+            let address = byte.wrapping_add(x);
+            format!("${:02X},X @ {:02X} = {:02X}", byte, address, peek(address as u16))
+        }
+        AddressingMode::ZeroPageY => {
+            let address = byte.wrapping_add(y);
+            format!("${:02X},Y @ {:02X} = {:02X}", byte, address, peek(address as u16))
+        }
+        AddressingMode::Absolute => format!("${:04X} = {:02X}", word, peek(word)),
+        AddressingMode::AbsoluteX => {
+            let address = word.wrapping_add(x as u16);
+            format!("${:04X},X @ {:04X} = {:02X}", word, address, peek(address))
+        }
+        AddressingMode::AbsoluteY => {
+            let address = word.wrapping_add(y as u16);
+            format!("${:04X},Y @ {:04X} = {:02X}", word, address, peek(address))
+        }
+        AddressingMode::IndirectX => {
+            let pointer = byte.wrapping_add(x);
+            let address = peek_zero_page_u16(pointer);
+            format!(
+                "(${:02X},X) @ {:02X} = {:04X} = {:02X}",
+                byte,
+                pointer,
+                address,
+                peek(address)
+            )
+        }
+        AddressingMode::IndirectY => {
+            let base = peek_zero_page_u16(byte);
+            let address = base.wrapping_add(y as u16);
+            format!(
+                "(${:02X}),Y = {:04X} @ {:04X} = {:02X}",
+                byte,
+                base,
+                address,
+                peek(address)
+            )
+        }
+        AddressingMode::NoneAddressing => match (code, len) {
+            // ASL, LSR, ROL, ROR on the accumulator
+            (0x0a | 0x4a | 0x2a | 0x6a, _) => "A".to_string(),
+            // branches
+            (_, 2) => format!("${:04X}", pc.wrapping_add(2).wrapping_add(byte as i8 as u16)),
+            // JMP indirect, with the page wrap bug
+            (0x6c, _) => {
+                let high = (word & 0xFF00) | (word.wrapping_add(1) & 0x00FF);
+                let target = u16::from_le_bytes([peek(word), peek(high)]);
+                format!("(${:04X}) = {:04X}", word, target)
+            }
+            // JMP, JSR
+            (_, 3) => format!("${:04X}", word),
+            _ => String::new(),
+        },
+    };
+
+    let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+    let instruction = format!("{:04X}  {:8} {:>4} {}", pc, hex.join(" "), mnemonic, operand);
+    let ppu = bus.ppu();
+    format!(
+        "{:47} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:3},{:3} CYC:{}",
+        instruction.trim_end(),
+        cpu.register_a(),
+        x,
+        y,
+        cpu.flags().bits(),
+        cpu.stack_pointer(),
+        ppu.scanline(),
+        ppu.dot(),
+        bus.cycles()
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bus::{Bus, Memory};
+
+    #[test]
+    fn test_trace_format() {
+        let mut bus = Bus::new();
+        bus.mem_write_u16(0x33, 0x0400);
+        bus.mem_write(0x0400, 0xAA);
+        // LDX #$01, ORA ($32,X), then an opcode the CPU doesn't know
+        for (i, byte) in [0xA2, 0x01, 0x01, 0x32, 0x02].iter().enumerate() {
+            bus.mem_write(0x0600 + i as u16, *byte);
+        }
+        let mut cpu = Cpu::new(bus);
+        cpu.program_counter = 0x0600;
+        assert_eq!(
+            trace(&cpu),
+            "0600  A2 01     LDX #$01                        A:00 X:00 Y:00 P:24 SP:00 PPU:  0,  0 CYC:0"
+        );
+        cpu.step().unwrap();
+        assert_eq!(
+            trace(&cpu),
+            "0602  01 32     ORA ($32,X) @ 33 = 0400 = AA    A:00 X:01 Y:00 P:24 SP:00 PPU:  0,  6 CYC:2"
+        );
+        cpu.step().unwrap();
+        assert_eq!(
+            trace(&cpu),
+            "0604  02        ???                             A:AA X:01 Y:00 P:A4 SP:00 PPU:  0, 24 CYC:8"
+        );
+        assert!(cpu.step().is_err());
+        assert_eq!(cpu.program_counter, 0x0604);
+    }
+}
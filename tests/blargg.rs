@@ -0,0 +1,229 @@
+//! Runs blargg's test ROMs, which report through PRG RAM https://www.nesdev.org/wiki/Emulator_tests:
+//! $6000 holds the status ($80 running, $81 press reset, below $80 the result code, 0 meaning passed),
+//! and once $6001-$6003 hold the signature DE B0 61, $6004 holds the zero terminated message text.
+//!
+//! The ROMs are looked up in `NES_TEST_ROMS` (see `common::rom_dir`), missing ones are skipped.
+//!
+//! The ROMs known to fail are in separate ignored tests, whose reason says what the emulator lacks (run them
+//! with `cargo test --test blargg -- --ignored`): unofficial opcodes, the interrupt polling details and the
+//! position of each read and write within its instruction, which the timing checks rely on.
+
+mod common;
+
+use std::panic::{self, AssertUnwindSafe};
+
+use rust_nes::bus::Memory;
+use rust_nes::nes::Nes;
+
+const STATUS: u16 = 0x6000;
+const SIGNATURE: u16 = 0x6001;
+const TEXT: u16 = 0x6004;
+
+const RUNNING: u8 = 0x80;
+const RESET_REQUEST: u8 = 0x81;
+
+/// Emulated seconds before giving up on a ROM
+const TIMEOUT_SECONDS: f64 = 60.0;
+/// The ROMs want the reset button pressed at least 100ms after they ask for it
+const RESET_DELAY_SECONDS: f64 = 0.1;
+
+enum Outcome {
+    Passed,
+    /// Result code and message
+    Failed(u8, String),
+    /// The ROM never reported a result: timeout, unsupported opcode...
+    Error(String),
+}
+
+fn read_text(nes: &Nes) -> String {
+    let bus = nes.bus();
+    let bytes: Vec<u8> = (TEXT..0x8000)
+        .map(|address| bus.mem_read(address))
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim().to_string()
+}
+
+fn has_signature(nes: &Nes) -> bool {
+    (0..3).map(|i| nes.bus().mem_read(SIGNATURE + i)).eq([0xDE, 0xB0, 0x61])
+}
+
+/// Runs the ROM until it writes a result, pressing reset whenever it asks for it
+fn run(nes: &mut Nes) -> Outcome {
+    let frame_rate = nes.bus().region().frame_rate();
+    let timeout = (TIMEOUT_SECONDS * frame_rate) as u64;
+    let reset_delay = (RESET_DELAY_SECONDS * frame_rate).ceil() as u64;
+    let mut reset_at = None;
+    while nes.frame() < timeout {
+        if let Err(error) = nes.run_frame() {
+            return Outcome::Error(error);
+        }
+        if !has_signature(nes) {
+            continue;
+        }
+        match nes.bus().mem_read(STATUS) {
+            RUNNING => {}
+            RESET_REQUEST => match reset_at {
+                None => reset_at = Some(nes.frame() + reset_delay),
+                Some(frame) if nes.frame() >= frame => {
+                    nes.reset();
+                    reset_at = None;
+                }
+                Some(_) => {}
+            },
+            0 => return Outcome::Passed,
+            code => return Outcome::Failed(code, read_text(nes)),
+        }
+    }
+    let text = if has_signature(nes) {
+        read_text(nes)
+    } else {
+        String::new()
+    };
+    Outcome::Error(format!("no result after {} seconds {}", TIMEOUT_SECONDS, text))
+}
+
+/// Runs the ROMs present under `directory` and fails listing every one that didn't pass
+fn run_suite(directory: &str, roms: &[&str]) {
+    let mut failures = Vec::new();
+    for rom in roms {
+        let relative = format!("{}/{}", directory, rom);
+        let Some(path) = common::test_rom(&relative) else {
+            continue;
+        };
+        let outcome = match common::load(&path) {
+            Ok(mut nes) => panic::catch_unwind(AssertUnwindSafe(|| run(&mut nes)))
+                .unwrap_or_else(|_| Outcome::Error("emulator panicked".to_string())),
+            Err(error) => Outcome::Error(error),
+        };
+        match outcome {
+            Outcome::Passed => eprintln!("{}: passed", relative),
+            Outcome::Failed(code, text) => failures.push(format!("{}: failed with {}\n{}", relative, code, text)),
+            Outcome::Error(error) => failures.push(format!("{}: {}", relative, error)),
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n\n"));
+}
+
+#[test]
+fn test_instr_test_v5() {
+    run_suite(
+        "instr_test-v5/rom_singles",
+        &[
+            "01-basics.nes",
+            "10-branches.nes",
+            "11-stack.nes",
+            "12-jmp_jsr.nes",
+            "13-rts.nes",
+            "14-rti.nes",
+            "15-brk.nes",
+            "16-special.nes",
+        ],
+    );
+}
+
+#[test]
+#[ignore = "these also test unofficial opcodes, which the CPU reports as unknown"]
+fn test_instr_test_v5_unofficial() {
+    run_suite(
+        "instr_test-v5/rom_singles",
+        &[
+            "02-implied.nes",
+            "03-immediate.nes",
+            "04-zero_page.nes",
+            "05-zp_xy.nes",
+            "06-absolute.nes",
+            "07-abs_xy.nes",
+            "08-ind_x.nes",
+            "09-ind_y.nes",
+        ],
+    );
+}
+
+#[test]
+#[ignore = "interrupts are polled before every instruction, without the CLI/SEI/PLP delay or NMI hijacking BRK"]
+fn test_cpu_interrupts() {
+    run_suite(
+        "cpu_interrupts_v2/rom_singles",
+        &[
+            "1-cli_latency.nes",
+            "2-nmi_and_brk.nes",
+            "3-nmi_and_irq.nes",
+            "4-irq_and_dma.nes",
+            "5-branch_delays_irq.nes",
+        ],
+    );
+}
+
+#[test]
+fn test_ppu_vbl_nmi() {
+    run_suite("ppu_vbl_nmi/rom_singles", &["01-vbl_basics.nes"]);
+}
+
+#[test]
+#[ignore = "the CPU reads and writes at the start of an instruction rather than on its own cycle"]
+fn test_ppu_vbl_nmi_timing() {
+    run_suite(
+        "ppu_vbl_nmi/rom_singles",
+        &[
+            "02-vbl_set_time.nes",
+            "03-vbl_clear_time.nes",
+            "04-nmi_control.nes",
+            "05-nmi_timing.nes",
+            "06-suppression.nes",
+            "07-nmi_on_timing.nes",
+            "08-nmi_off_timing.nes",
+            "09-even_odd_frames.nes",
+            "10-even_odd_timing.nes",
+        ],
+    );
+}
+
+#[test]
+fn test_apu_test() {
+    run_suite(
+        "apu_test/rom_singles",
+        &["1-len_ctr.nes", "2-len_table.nes", "3-irq_flag.nes", "7-dmc_basics.nes"],
+    );
+}
+
+#[test]
+#[ignore = "the CPU reads and writes at the start of an instruction rather than on its own cycle"]
+fn test_apu_test_timing() {
+    run_suite(
+        "apu_test/rom_singles",
+        &[
+            "4-jitter.nes",
+            "5-len_timing.nes",
+            "6-irq_flag_timing.nes",
+            "8-dmc_rates.nes",
+        ],
+    );
+}
+
+/// Asks for a reset, then reports result 1 with the text "OK"
+#[rustfmt::skip]
+const RESET_THEN_FAIL: [u8; 53] = [
+    0xAD, 0x00, 0x60,             // LDA $6000
+    0xC9, 0x81,                   // CMP #$81
+    0xF0, 0x17,                   // BEQ report
+    0xA9, 0xDE, 0x8D, 0x01, 0x60, // LDA #$DE, STA $6001
+    0xA9, 0xB0, 0x8D, 0x02, 0x60, // LDA #$B0, STA $6002
+    0xA9, 0x61, 0x8D, 0x03, 0x60, // LDA #$61, STA $6003
+    0xA9, 0x81, 0x8D, 0x00, 0x60, // LDA #$81, STA $6000
+    0x4C, 0x1B, 0xC0,             // JMP $C01B
+    // report at $C01E
+    0xA9, 0x4F, 0x8D, 0x04, 0x60, // LDA #'O', STA $6004
+    0xA9, 0x4B, 0x8D, 0x05, 0x60, // LDA #'K', STA $6005
+    0xA9, 0x00, 0x8D, 0x06, 0x60, // LDA #0, STA $6006
+    0xA9, 0x01, 0x8D, 0x00, 0x60, // LDA #1, STA $6000
+    0x4C, 0x32, 0xC0,             // JMP $C032
+];
+
+#[test]
+fn test_status_protocol() {
+    let mut nes = Nes::new(&common::nrom_image(&RESET_THEN_FAIL, 0xC000)).unwrap();
+    let outcome = run(&mut nes);
+    assert!(matches!(outcome, Outcome::Failed(1, ref text) if text == "OK"));
+    assert!(nes.frame() >= 6);
+}
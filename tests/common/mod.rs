@@ -0,0 +1,44 @@
+//! Helpers shared by the integration tests
+
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+use rust_nes::cartridge::Cartridge;
+use rust_nes::nes::Nes;
+
+/// Test ROMs are not part of the repository. Point `NES_TEST_ROMS` at a checkout of
+/// https://github.com/christopherpow/nes-test-roms, by default they are looked up in `tests/roms`.
+pub fn rom_dir() -> PathBuf {
+    match std::env::var_os("NES_TEST_ROMS") {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("roms"),
+    }
+}
+
+/// Path of a test ROM under `rom_dir()`, None (with a note on stderr) when it isn't there
+pub fn test_rom(relative: &str) -> Option<PathBuf> {
+    let path = rom_dir().join(relative);
+    if path.is_file() {
+        Some(path)
+    } else {
+        eprintln!("skipping {}: not found in {}", relative, rom_dir().display());
+        None
+    }
+}
+
+pub fn load(path: &Path) -> Result<Nes, String> {
+    let (cartridge, _) = Cartridge::load(path, &[], &|_| None)?;
+    Nes::new(&cartridge)
+}
+
+/// 16KB NROM image running `program` from $C000, with `nmi` as the NMI handler address
+pub fn nrom_image(program: &[u8], nmi: u16) -> Cartridge {
+    let mut raw = vec![0x4E, 0x45, 0x53, 0x1A, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+    let mut prg = vec![0; 0x4000];
+    prg[..program.len()].copy_from_slice(program);
+    prg[0x3FFA..0x3FFE].copy_from_slice(&[nmi as u8, (nmi >> 8) as u8, 0x00, 0xC0]);
+    raw.extend(prg);
+    raw.extend(vec![0; 0x2000]);
+    Cartridge::parse(&raw).unwrap()
+}
@@ -0,0 +1,73 @@
+//! nestest.nes in automation mode https://www.qmtpro.com/~nes/misc/nestest.txt: started at $C000 instead of
+//! its reset vector, it runs every CPU test without needing a screen or controller. The trace is compared
+//! line by line with the reference log from Nintendulator, `other/nestest.log` next to the ROM.
+//!
+//! The CPU has no unofficial opcodes yet, so the comparison stops at the first one, which the log marks with
+//! a `*` before the mnemonic: by then every official opcode has been tested.
+
+mod common;
+
+use std::fs;
+
+use rust_nes::nes::Nes;
+use rust_nes::trace::trace;
+
+const AUTOMATION_START: u16 = 0xC000;
+/// Log lines shown before the first difference
+const CONTEXT_LINES: usize = 5;
+
+/// Column of the `*` marking unofficial opcodes in the log
+const UNOFFICIAL_MARK: usize = 15;
+
+/// nestest writes the number of the last failed test to $02 (official opcodes) and $03 (unofficial ones)
+fn error_codes(nes: &Nes) -> String {
+    let peek = |address| nes.bus().peek(address).unwrap_or(0);
+    format!("error codes $02={:02X} $03={:02X}", peek(0x02), peek(0x03))
+}
+
+#[test]
+fn test_nestest_log() {
+    let (Some(rom), Some(log)) = (
+        common::test_rom("other/nestest.nes"),
+        common::test_rom("other/nestest.log"),
+    ) else {
+        return;
+    };
+    let log = fs::read_to_string(log).unwrap();
+    let expected: Vec<&str> = log
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty())
+        .collect();
+
+    let mut nes = common::load(&rom).unwrap();
+    // the reset took its 7 cycles, leaving the registers as the log starts: P:24 SP:FD PPU: 0, 21 CYC:7
+    nes.cpu_mut().program_counter = AUTOMATION_START;
+    for (number, expected_line) in expected.iter().enumerate() {
+        if expected_line.as_bytes().get(UNOFFICIAL_MARK) == Some(&b'*') {
+            assert_eq!(
+                nes.bus().peek(0x02),
+                Some(0),
+                "official opcodes failed ({})",
+                error_codes(&nes)
+            );
+            return;
+        }
+        let line = trace(nes.cpu());
+        if line != *expected_line {
+            let context = &expected[number.saturating_sub(CONTEXT_LINES)..number];
+            panic!(
+                "trace differs at line {} ({}):\n{}\nexpected: {}\nactual:   {}",
+                number + 1,
+                error_codes(&nes),
+                context.join("\n"),
+                expected_line,
+                line
+            );
+        }
+        if let Err(error) = nes.cpu_mut().step() {
+            panic!("{} after line {} ({})", error, number + 1, error_codes(&nes));
+        }
+    }
+    assert_eq!(error_codes(&nes), "error codes $02=00 $03=00");
+}
@@ -0,0 +1,175 @@
+//! Screenshot regression tests: run a ROM for a number of frames with scripted input and compare the last
+//! frame with a golden PNG in `tests/screenshots`.
+//!
+//! Set `UPDATE_SCREENSHOTS=1` to record missing or changed goldens. On a mismatch the actual frame and a
+//! diff picture (differences in red) go to `target/screenshot-diffs`.
+
+mod common;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use rust_nes::image;
+use rust_nes::input_script::InputScript;
+use rust_nes::nes::Nes;
+use rust_nes::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+struct Case {
+    name: &'static str,
+    /// Relative to `common::rom_dir()`
+    rom: &'static str,
+    frames: u64,
+    input: &'static str,
+}
+
+/// Visual test ROMs, which only show their result on screen
+const CASES: &[Case] = &[
+    Case {
+        name: "sprite_hit_basics",
+        rom: "sprite_hit_tests_2005.10.05/01.basics.nes",
+        frames: 60,
+        input: "",
+    },
+    Case {
+        name: "sprite_hit_alignment",
+        rom: "sprite_hit_tests_2005.10.05/02.alignment.nes",
+        frames: 60,
+        input: "",
+    },
+    Case {
+        name: "sprite_hit_corners",
+        rom: "sprite_hit_tests_2005.10.05/03.corners.nes",
+        frames: 60,
+        input: "",
+    },
+    Case {
+        name: "sprite_hit_flip",
+        rom: "sprite_hit_tests_2005.10.05/04.flip.nes",
+        frames: 60,
+        input: "",
+    },
+    Case {
+        name: "sprite_hit_left_clip",
+        rom: "sprite_hit_tests_2005.10.05/05.left_clip.nes",
+        frames: 60,
+        input: "",
+    },
+    Case {
+        name: "sprite_hit_right_edge",
+        rom: "sprite_hit_tests_2005.10.05/06.right_edge.nes",
+        frames: 60,
+        input: "",
+    },
+    Case {
+        name: "sprite_hit_double_height",
+        rom: "sprite_hit_tests_2005.10.05/08.double_height.nes",
+        frames: 60,
+        input: "",
+    },
+    Case {
+        name: "scanline",
+        rom: "scanline/scanline.nes",
+        frames: 120,
+        input: "",
+    },
+];
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("screenshots")
+}
+
+fn diff_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("target")
+        .join("screenshot-diffs")
+}
+
+/// Runs `frames` frames and compares the picture with the golden `name`.png
+fn check(name: &str, nes: &mut Nes, frames: u64, input: &str) -> Result<(), String> {
+    let script = InputScript::parse(input)?;
+    for _ in 0..frames {
+        nes.apply_input(&script);
+        nes.run_frame()?;
+    }
+    let actual = nes.rgb_frame();
+    let golden = golden_dir().join(format!("{}.png", name));
+    let record = std::env::var_os("UPDATE_SCREENSHOTS").is_some();
+    if !golden.exists() && !record {
+        eprintln!(
+            "skipping {}: no golden {}, run with UPDATE_SCREENSHOTS=1 to record it",
+            name,
+            golden.display()
+        );
+        return Ok(());
+    }
+    if golden.exists() {
+        let (width, height, expected) = image::read_png(&golden)?;
+        if (width, height) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+            return Err(format!("{}: golden is {}x{}", name, width, height));
+        }
+        let (differences, picture) = image::diff(&expected, &actual);
+        if differences == 0 {
+            return Ok(());
+        }
+        if !record {
+            fs::create_dir_all(diff_dir()).map_err(|e| e.to_string())?;
+            let actual_path = diff_dir().join(format!("{}.actual.png", name));
+            let diff_path = diff_dir().join(format!("{}.diff.png", name));
+            image::write_png(&actual_path, SCREEN_WIDTH, SCREEN_HEIGHT, &actual)?;
+            image::write_png(&diff_path, SCREEN_WIDTH, SCREEN_HEIGHT, &picture)?;
+            return Err(format!(
+                "{}: {} pixels differ, see {}",
+                name,
+                differences,
+                diff_path.display()
+            ));
+        }
+    }
+    fs::create_dir_all(golden_dir()).map_err(|e| e.to_string())?;
+    image::write_png(&golden, SCREEN_WIDTH, SCREEN_HEIGHT, &actual)?;
+    eprintln!("recorded {}", golden.display());
+    Ok(())
+}
+
+#[test]
+fn test_rom_screenshots() {
+    let failures: Vec<String> = CASES
+        .iter()
+        .filter_map(|case| {
+            let path = common::test_rom(case.rom)?;
+            let result = common::load(&path).and_then(|mut nes| check(case.name, &mut nes, case.frames, case.input));
+            result.err()
+        })
+        .collect();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+/// Sets the backdrop to green while A is held and to red otherwise, from its NMI handler
+#[rustfmt::skip]
+const JOYPAD_BACKDROP: [u8; 56] = [
+    0xA9, 0x80, 0x8D, 0x00, 0x20, // LDA #$80, STA $2000: NMI on
+    0xA9, 0x08, 0x8D, 0x01, 0x20, // LDA #$08, STA $2001: background on
+    0x4C, 0x0A, 0xC0,             // JMP $C00A
+    // NMI at $C00D: read A from controller 1
+    0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #$01, STA $4016
+    0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #$00, STA $4016
+    0xAD, 0x16, 0x40, 0x29, 0x01, // LDA $4016, AND #$01
+    0xF0, 0x04,                   // BEQ red
+    0xA9, 0x2A, 0xD0, 0x02,       // LDA #$2A, BNE store
+    0xA9, 0x16,                   // red: LDA #$16
+    0xA2, 0x3F, 0x8E, 0x06, 0x20, // store: LDX #$3F, STX $2006
+    0xA2, 0x00, 0x8E, 0x06, 0x20, // LDX #$00, STX $2006
+    0x8D, 0x07, 0x20,             // STA $2007
+    0x8E, 0x06, 0x20,             // STX $2006
+    0x8E, 0x06, 0x20,             // STX $2006
+    0x40,                         // RTI
+];
+
+#[test]
+fn test_scripted_input_screenshots() {
+    let cartridge = common::nrom_image(&JOYPAD_BACKDROP, 0xC00D);
+    let mut idle = Nes::new(&cartridge).unwrap();
+    check("joypad_backdrop_idle", &mut idle, 10, "").unwrap();
+    let mut pressed = Nes::new(&cartridge).unwrap();
+    check("joypad_backdrop_a", &mut pressed, 10, "0 -; 5 a").unwrap();
+}